@@ -4,3 +4,4 @@ set -e
 
 cargo build-bpf --manifest-path=src/geonft_solana/Cargo.toml
 solana program deploy target/deploy/geonft_solana.so
+echo "program id for geonft_sync: $(solana address -k target/deploy/geonft_solana-keypair.json)"
@@ -0,0 +1,240 @@
+//! Passphrase-encrypted account secret key backups
+//!
+//! A backup is bech32m encoded with the `gae` HRP,
+//! so it can be copied as text or printed as a QR code.
+//! The encoded bytes are
+//!
+//! - version, 1 byte, currently 1
+//! - scrypt log2(N), 1 byte, with r = 8 and p = 1
+//! - scrypt salt, 16 bytes
+//! - ChaCha20Poly1305 nonce, 12 bytes
+//! - the encrypted secret key and tag, 48 bytes
+//!
+//! The key is encrypted with the scrypt-derived key,
+//! and the header bytes before it are authenticated.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, Result};
+use bech32::{FromBase32, ToBase32, Variant};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{CryptoRng, RngCore};
+
+use crate::crypto::{ResultWrapper, SecretKey};
+
+pub static ACCOUNT_BACKUP_HRP: &'static str = "gae";
+
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 2 + SALT_LEN + NONCE_LEN;
+
+/// scrypt cost for new backups: 32 MiB, a second or so in a browser
+pub const DEFAULT_LOG_N: u8 = 15;
+
+/// Backups outside this range are rejected,
+/// so decoding can't be made to exhaust memory.
+const MIN_LOG_N: u8 = 10;
+const MAX_LOG_N: u8 = 18;
+
+/// Encrypt an account secret key with a random salt and nonce.
+pub fn encrypt_account_secret_key(
+    key: &SecretKey,
+    passphrase: &str,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<String> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    encrypt_account_secret_key_with_params(key, passphrase, DEFAULT_LOG_N, &salt, &nonce)
+}
+
+/// Like `encrypt_account_secret_key`, but deterministic.
+///
+/// Never reuse a salt and nonce with the same passphrase.
+pub fn encrypt_account_secret_key_with_params(
+    key: &SecretKey,
+    passphrase: &str,
+    log_n: u8,
+    salt: &[u8; SALT_LEN],
+    nonce: &[u8; NONCE_LEN],
+) -> Result<String> {
+    if passphrase.is_empty() {
+        bail!("backup passphrase is empty");
+    }
+    check_log_n(log_n)?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.push(VERSION);
+    header.push(log_n);
+    header.extend_from_slice(salt);
+    header.extend_from_slice(nonce);
+
+    let cipher = new_cipher(passphrase, log_n, salt)?;
+    let key_bytes = key.to_bytes();
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: key_bytes.as_slice(),
+                aad: &header,
+            },
+        )
+        .map_err(|_| anyhow!("unable to encrypt account secret key"))?;
+
+    let mut bytes = header;
+    bytes.extend_from_slice(&ciphertext);
+
+    let encoded = bech32::encode(ACCOUNT_BACKUP_HRP, bytes.to_base32(), Variant::Bech32m).e()?;
+    Ok(encoded)
+}
+
+pub fn decrypt_account_secret_key(backup: &str, passphrase: &str) -> Result<SecretKey> {
+    let (hrp, data, variant) = bech32::decode(backup.trim()).e()?;
+
+    if hrp != ACCOUNT_BACKUP_HRP {
+        bail!("wrong HRP in account backup decoding");
+    }
+
+    if variant != Variant::Bech32m {
+        bail!("wrong bech32 variant in account backup decoding");
+    }
+
+    let bytes = Vec::<u8>::from_base32(&data).e()?;
+    if bytes.len() <= HEADER_LEN {
+        bail!("account backup too short");
+    }
+
+    let (header, ciphertext) = bytes.split_at(HEADER_LEN);
+    let version = header[0];
+    let log_n = header[1];
+    let salt = &header[2..2 + SALT_LEN];
+    let nonce = &header[2 + SALT_LEN..];
+
+    if version != VERSION {
+        bail!("unsupported account backup version {}", version);
+    }
+    check_log_n(log_n)?;
+
+    let cipher = new_cipher(passphrase, log_n, salt)?;
+    let key_bytes = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| anyhow!("wrong passphrase or corrupt account backup"))?;
+
+    let key = SecretKey::from_bytes(&key_bytes).e()?;
+    Ok(key)
+}
+
+fn check_log_n(log_n: u8) -> Result<()> {
+    if !(MIN_LOG_N..=MAX_LOG_N).contains(&log_n) {
+        bail!(
+            "scrypt cost must be between {} and {}",
+            MIN_LOG_N,
+            MAX_LOG_N
+        );
+    }
+    Ok(())
+}
+
+fn new_cipher(passphrase: &str, log_n: u8, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let params =
+        scrypt::Params::new(log_n, 8, 1).map_err(|_| anyhow!("invalid scrypt parameters"))?;
+    let mut key = [0; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| anyhow!("invalid scrypt output length"))?;
+
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto;
+
+    static ACCOUNT_SECRET_KEY: &str =
+        "gas1zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygsk4s25s";
+
+    /// (passphrase, salt, nonce, backup) with log_n = 10
+    static VECTORS: &[(&str, [u8; SALT_LEN], [u8; NONCE_LEN], &str)] = &[
+        (
+            "correct horse battery staple",
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+            [100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111],
+            "gae1qy9qqqgzqvzq2ps8pqys5zcvp58q7er9venks6t2ddkx6mn0ljmgynxvuxgqx8ckzz3zmf5rscm5dwjclw8uzsf066sfry2wpky50evjz8awwh03532nh38qyzpzqnys34c",
+        ),
+        (
+            "pässwörd",
+            [0xaa; SALT_LEN],
+            [0x55; NONCE_LEN],
+            "gae1qy92424242424242424242424242542424242424242424249wxku08r7a4kh5d6agemagx7tuatw5qkkx8szkx88xqvk5qhzfeetkrtqxtlrxgr69wwdveqfsx42rqxt8c",
+        ),
+    ];
+
+    #[test]
+    fn encrypt_test_vectors() {
+        let key = crypto::decode_account_secret_key(ACCOUNT_SECRET_KEY).unwrap();
+        for (passphrase, salt, nonce, expected) in VECTORS {
+            let backup =
+                encrypt_account_secret_key_with_params(&key, passphrase, 10, salt, nonce).unwrap();
+            assert_eq!(backup, *expected);
+        }
+    }
+
+    #[test]
+    fn decrypt_test_vectors() {
+        for (passphrase, _, _, backup) in VECTORS {
+            let key = decrypt_account_secret_key(backup, passphrase).unwrap();
+            let key = crypto::encode_account_secret_key(&key).unwrap();
+            assert_eq!(key, ACCOUNT_SECRET_KEY);
+        }
+    }
+
+    #[test]
+    fn decrypt_uppercase() {
+        let (passphrase, _, _, backup) = VECTORS[0];
+        let backup = backup.to_uppercase();
+        assert!(decrypt_account_secret_key(&backup, passphrase).is_ok());
+    }
+
+    #[test]
+    fn decrypt_wrong_passphrase() {
+        let (_, _, _, backup) = VECTORS[0];
+        assert!(decrypt_account_secret_key(backup, "incorrect horse").is_err());
+    }
+
+    #[test]
+    fn decrypt_tampered_header() {
+        let (passphrase, salt, nonce, _) = VECTORS[0];
+        let key = crypto::decode_account_secret_key(ACCOUNT_SECRET_KEY).unwrap();
+        let backup =
+            encrypt_account_secret_key_with_params(&key, passphrase, 11, &salt, &nonce).unwrap();
+
+        // Re-encode with the cost lowered, keeping the ciphertext
+        let (_, data, _) = bech32::decode(&backup).unwrap();
+        let mut bytes = Vec::<u8>::from_base32(&data).unwrap();
+        bytes[1] = 10;
+        let tampered =
+            bech32::encode(ACCOUNT_BACKUP_HRP, bytes.to_base32(), Variant::Bech32m).unwrap();
+
+        assert!(decrypt_account_secret_key(&tampered, passphrase).is_err());
+    }
+
+    #[test]
+    fn reject_expensive_params() {
+        let (passphrase, salt, nonce, _) = VECTORS[0];
+        let key = crypto::decode_account_secret_key(ACCOUNT_SECRET_KEY).unwrap();
+        assert!(
+            encrypt_account_secret_key_with_params(&key, passphrase, 30, &salt, &nonce).is_err()
+        );
+    }
+}
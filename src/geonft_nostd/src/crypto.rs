@@ -1,7 +1,7 @@
 use alloc::format;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 
 //pub use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer};
 pub use k256::ecdsa::signature::{Signature as SignatureTrait, Signer, Verifier};
@@ -9,6 +9,9 @@ pub use k256::ecdsa::{Signature, SigningKey as SecretKey, VerifyingKey as Public
 
 use base64;
 use bech32::{FromBase32, ToBase32, Variant};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha512;
 use sha256::digest_bytes;
 
 use rand::{CryptoRng, RngCore};
@@ -17,12 +20,28 @@ pub static ACCOUNT_SECRET_KEY_HRP: &'static str = "gas";
 pub static ACCOUNT_PUBLIC_KEY_HRP: &'static str = "gap";
 pub static TREASURE_SECRET_KEY_HRP: &'static str = "gts";
 pub static TREASURE_PUBLIC_KEY_HRP: &'static str = "gtp";
-pub static TREASURE_SECRET_PLANT_URL_PREFIX: &'static str = "https://treasuretree.org/plant?key=";
-pub static TREASURE_SECRET_CLAIM_URL_PREFIX: &'static str = "https://treasuretree.org/claim?key=";
-pub static TREASURE_SECRET_PLANT_URL_PREFIX_LOCAL: &'static str =
-    "http://127.0.0.1:8000/plant?key=";
-pub static TREASURE_SECRET_CLAIM_URL_PREFIX_LOCAL: &'static str =
-    "http://127.0.0.1:8000/claim?key=";
+pub static PLANTER_SEED_HRP: &'static str = "gps";
+/// Paths of URLs containing a treasure secret key,
+/// relative to the origin of the site.
+///
+/// The compact path is uppercase so that, with an uppercase origin and key,
+/// every character of the URL is in the QR alphanumeric set,
+/// which needs fewer modules than byte mode.
+/// bech32 keys may be written in uppercase.
+pub static TREASURE_SECRET_PLANT_PATH: &'static str = "/plant?key=";
+pub static TREASURE_SECRET_CLAIM_PATH: &'static str = "/claim?key=";
+pub static TREASURE_SECRET_COMPACT_CLAIM_PATH: &'static str = "/C/";
+
+/// HMAC key for deriving an account secret key from a BIP39 seed
+pub static ACCOUNT_KEY_DERIVATION_DOMAIN: &'static [u8] = b"TreasureTree account";
+
+/// HMAC key for deriving treasure secret keys from a planter seed
+pub static TREASURE_KEY_DERIVATION_DOMAIN: &'static [u8] = b"TreasureTree treasure";
+
+const PLANTER_SEED_LEN: usize = 32;
+
+/// Entropy of new mnemonics, 12 words
+const MNEMONIC_ENTROPY_LEN: usize = 16;
 
 pub struct Keypair {
     pub secret: SecretKey,
@@ -61,6 +80,106 @@ pub fn keypair_from_account_secret_key(key: &str) -> Result<Keypair> {
     Ok(keypair)
 }
 
+/// A new 12 word BIP39 mnemonic, from which an account key can be derived.
+pub fn new_account_mnemonic(rng: &mut (impl CryptoRng + RngCore)) -> Result<String> {
+    let mut entropy = [0; MNEMONIC_ENTROPY_LEN];
+    rng.fill_bytes(&mut entropy);
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| anyhow!("{}", e))?;
+    Ok(mnemonic.to_string())
+}
+
+/// Derive an account secret key from a BIP39 mnemonic, with no passphrase.
+///
+/// The key is derived from the BIP39 seed with
+/// HMAC-SHA512 keyed by `ACCOUNT_KEY_DERIVATION_DOMAIN`.
+/// Words may be in any case and separated by any whitespace.
+pub fn account_secret_key_from_mnemonic(phrase: &str) -> Result<SecretKey> {
+    let phrase = phrase
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    // The English wordlist is ASCII, and so already normalized,
+    // as is the empty passphrase.
+    let mnemonic = Mnemonic::parse_normalized(&phrase).map_err(|e| anyhow!("{}", e))?;
+    let seed = mnemonic.to_seed_normalized("");
+    derive_secret_key(ACCOUNT_KEY_DERIVATION_DOMAIN, &seed)
+}
+
+pub fn keypair_from_account_mnemonic(phrase: &str) -> Result<Keypair> {
+    let secret_key = account_secret_key_from_mnemonic(phrase)?;
+    let public_key = PublicKey::from(&secret_key);
+    let keypair = Keypair {
+        secret: secret_key,
+        public: public_key,
+    };
+    Ok(keypair)
+}
+
+/// Derive a secret key from `data` with HMAC-SHA512 keyed by `domain`.
+///
+/// The first half of the MAC is the key,
+/// unless it is out of range for secp256k1, in which case the second half is.
+/// Both being out of range is vanishingly unlikely.
+pub fn derive_secret_key(domain: &[u8], data: &[u8]) -> Result<SecretKey> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(domain).map_err(|e| anyhow!("{}", e))?;
+    mac.update(data);
+    let bytes = mac.finalize().into_bytes();
+
+    bytes
+        .chunks(32)
+        .find_map(|half| SecretKey::from_bytes(half).ok())
+        .ok_or_else(|| anyhow!("derived secret key out of range"))
+}
+
+/// A new planter seed, from which any number of treasure keys can be derived.
+///
+/// Anyone with the seed can claim every treasure derived from it.
+pub fn new_planter_seed(rng: &mut (impl CryptoRng + RngCore)) -> Result<String> {
+    let mut seed = [0; PLANTER_SEED_LEN];
+    rng.fill_bytes(&mut seed);
+    let encoded = bech32::encode(PLANTER_SEED_HRP, seed.to_base32(), Variant::Bech32m).e()?;
+    Ok(encoded)
+}
+
+pub fn decode_planter_seed(seed: &str) -> Result<Vec<u8>> {
+    let (actual_hrp, data, variant) = bech32::decode(seed).e()?;
+
+    if actual_hrp != PLANTER_SEED_HRP {
+        bail!("wrong HRP in planter seed decoding");
+    }
+
+    if variant != Variant::Bech32m {
+        bail!("wrong bech32 variant in planter seed decoding");
+    }
+
+    let bytes = Vec::<u8>::from_base32(&data).e()?;
+
+    if bytes.len() != PLANTER_SEED_LEN {
+        bail!("wrong planter seed length");
+    }
+
+    Ok(bytes)
+}
+
+/// The treasure keypair at `index` derived from a decoded planter seed.
+///
+/// The secret key is derived from the seed
+/// appended with the big-endian index,
+/// with HMAC-SHA512 keyed by `TREASURE_KEY_DERIVATION_DOMAIN`.
+pub fn derive_treasure_keypair(seed: &[u8], index: u32) -> Result<Keypair> {
+    let mut data = Vec::from(seed);
+    data.extend_from_slice(&index.to_be_bytes());
+
+    let secret_key = derive_secret_key(TREASURE_KEY_DERIVATION_DOMAIN, &data)?;
+    let public_key = PublicKey::from(&secret_key);
+    let keypair = Keypair {
+        secret: secret_key,
+        public: public_key,
+    };
+    Ok(keypair)
+}
+
 pub fn keypair_from_treasure_secret_key(key: &str) -> Result<Keypair> {
     let secret_key = decode_treasure_secret_key(key)?;
     let public_key = PublicKey::from(&secret_key);
@@ -71,27 +190,115 @@ pub fn keypair_from_treasure_secret_key(key: &str) -> Result<Keypair> {
     Ok(keypair)
 }
 
-pub fn keypair_to_treasure_secret_claim_url(keypair: &Keypair) -> Result<String> {
+/// The URL of the claim page for a treasure,
+/// on the site whose origin is `base_url`, e.g. `https://treasuretree.org`.
+pub fn keypair_to_treasure_secret_claim_url(keypair: &Keypair, base_url: &str) -> Result<String> {
     let secret_key_string = encode_treasure_secret_key(&keypair.secret)?;
-    let url = format!("{}{}", TREASURE_SECRET_CLAIM_URL_PREFIX, secret_key_string);
+    let url = format!(
+        "{}{}{}",
+        base_url.trim_end_matches('/'),
+        TREASURE_SECRET_CLAIM_PATH,
+        secret_key_string
+    );
     Ok(url)
 }
 
-pub fn treasure_secret_url_to_keypair(url: &str) -> Result<Keypair> {
-    if url.starts_with(TREASURE_SECRET_PLANT_URL_PREFIX) {
-        let key = url.split_at(TREASURE_SECRET_PLANT_URL_PREFIX.len()).1;
-        keypair_from_treasure_secret_key(key)
-    } else if url.starts_with(TREASURE_SECRET_CLAIM_URL_PREFIX) {
-        let key = url.split_at(TREASURE_SECRET_CLAIM_URL_PREFIX.len()).1;
-        keypair_from_treasure_secret_key(key)
-    } else if url.starts_with(TREASURE_SECRET_PLANT_URL_PREFIX_LOCAL) {
-        let key = url.split_at(TREASURE_SECRET_PLANT_URL_PREFIX_LOCAL.len()).1;
-        keypair_from_treasure_secret_key(key)
-    } else if url.starts_with(TREASURE_SECRET_CLAIM_URL_PREFIX_LOCAL) {
-        let key = url.split_at(TREASURE_SECRET_CLAIM_URL_PREFIX_LOCAL.len()).1;
-        keypair_from_treasure_secret_key(key)
+/// Like `keypair_to_treasure_secret_claim_url`,
+/// but in the uppercase form that encodes to a smaller QR code.
+pub fn keypair_to_treasure_secret_compact_claim_url(
+    keypair: &Keypair,
+    base_url: &str,
+) -> Result<String> {
+    let secret_key_string = encode_treasure_secret_key(&keypair.secret)?;
+    let url = format!(
+        "{}{}{}",
+        base_url.trim_end_matches('/'),
+        TREASURE_SECRET_COMPACT_CLAIM_PATH,
+        secret_key_string
+    );
+    Ok(url.to_uppercase())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TreasureSecretPath {
+    Plant,
+    Claim,
+    CompactClaim,
+}
+
+/// A URL containing a treasure secret key,
+/// like `https://treasuretree.org/claim?key=gts1...`.
+///
+/// URLs from any site are parsed;
+/// use `is_allowed_origin` to check the site is trusted.
+pub struct TreasureSecretUrl<'a> {
+    /// The scheme, host and port, e.g. `https://treasuretree.org`
+    pub origin: &'a str,
+    pub path: TreasureSecretPath,
+    pub key: &'a str,
+}
+
+impl<'a> TreasureSecretUrl<'a> {
+    /// Whether the origin is one of `allowed_origins`,
+    /// ignoring case and trailing slashes.
+    pub fn is_allowed_origin<S: AsRef<str>>(&self, allowed_origins: &[S]) -> bool {
+        allowed_origins.iter().any(|allowed| {
+            allowed
+                .as_ref()
+                .trim_end_matches('/')
+                .eq_ignore_ascii_case(self.origin)
+        })
+    }
+}
+
+/// Split an http or https URL into its origin and the rest,
+/// which is either empty or begins with `/`.
+pub fn split_origin(url: &str) -> Result<(&str, &str)> {
+    let scheme_len = ["https://", "http://"]
+        .iter()
+        .find(|scheme| strip_prefix_ignore_case(url, scheme).is_some())
+        .map(|scheme| scheme.len())
+        .ok_or_else(|| anyhow!("URL must begin with http:// or https://"))?;
+
+    let origin_len = url[scheme_len..]
+        .find('/')
+        .map(|i| scheme_len + i)
+        .unwrap_or(url.len());
+
+    if origin_len == scheme_len {
+        bail!("URL has no host");
+    }
+
+    Ok(url.split_at(origin_len))
+}
+
+pub fn parse_treasure_secret_url(url: &str) -> Result<TreasureSecretUrl> {
+    let (origin, rest) = split_origin(url)?;
+
+    let (path, key) = if let Some(key) = rest.strip_prefix(TREASURE_SECRET_PLANT_PATH) {
+        (TreasureSecretPath::Plant, key)
+    } else if let Some(key) = rest.strip_prefix(TREASURE_SECRET_CLAIM_PATH) {
+        (TreasureSecretPath::Claim, key)
+    } else if let Some(key) = strip_prefix_ignore_case(rest, TREASURE_SECRET_COMPACT_CLAIM_PATH) {
+        (TreasureSecretPath::CompactClaim, key)
     } else {
-        bail!("incorrect URL prefix for secret key");
+        bail!("incorrect URL path for secret key");
+    };
+
+    Ok(TreasureSecretUrl { origin, path, key })
+}
+
+pub fn treasure_secret_url_to_keypair(url: &str) -> Result<Keypair> {
+    let url = parse_treasure_secret_url(url)?;
+    keypair_from_treasure_secret_key(url.key)
+}
+
+/// Scanners may change the case of a URL, so schemes
+/// and the compact claim path are matched case-insensitively.
+pub fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
     }
 }
 
@@ -217,15 +424,19 @@ pub fn verify_plant_request_for_account(
 ///
 /// - "plant", appended with
 /// - the account public key, appended with
-/// - the hash of the treasure image
+/// - the hash of the treasure image, appended with
+/// - the signed bytes of the treasure details and claim window,
+///   empty if there are none
 pub fn sign_plant_request_for_treasure(
     treasure_secret_key: &SecretKey,
     account_public_key: &PublicKey,
     treasure_hash: &[u8],
+    details: &[u8],
 ) -> Result<Signature> {
     let mut message = Vec::from("plant");
     message.extend_from_slice(&account_public_key.to_bytes());
     message.extend_from_slice(treasure_hash);
+    message.extend_from_slice(details);
 
     create_signature(&message, treasure_secret_key)
 }
@@ -236,11 +447,13 @@ pub fn verify_plant_request_for_treasure(
     treasure_public_key: &PublicKey,
     account_public_key: &PublicKey,
     treasure_hash: &[u8],
+    details: &[u8],
     signature: &Signature,
 ) -> Result<()> {
     let mut message = Vec::from("plant");
     message.extend_from_slice(&account_public_key.to_bytes());
     message.extend_from_slice(treasure_hash);
+    message.extend_from_slice(details);
 
     verify_signature(&message, signature, treasure_public_key)
 }
@@ -248,13 +461,16 @@ pub fn verify_plant_request_for_treasure(
 /// With the account secret key, sign
 ///
 /// - "claim", appended with
-/// - the treasure public key
+/// - the treasure public key, appended with
+/// - the big-endian timestamp
 pub fn sign_claim_request_for_account(
     account_secret_key: &SecretKey,
     treasure_public_key: &PublicKey,
+    timestamp: u64,
 ) -> Result<Signature> {
     let mut message = Vec::from("claim");
     message.extend_from_slice(&treasure_public_key.to_bytes());
+    message.extend_from_slice(&timestamp.to_be_bytes());
 
     create_signature(&message, account_secret_key)
 }
@@ -264,10 +480,12 @@ pub fn sign_claim_request_for_account(
 pub fn verify_claim_request_for_account(
     account_public_key: &PublicKey,
     treasure_public_key: &PublicKey,
+    timestamp: u64,
     signature: &Signature,
 ) -> Result<()> {
     let mut message = Vec::from("claim");
     message.extend_from_slice(&treasure_public_key.to_bytes());
+    message.extend_from_slice(&timestamp.to_be_bytes());
 
     verify_signature(&message, signature, account_public_key)
 }
@@ -299,6 +517,188 @@ pub fn verify_claim_request_for_treasure(
     verify_signature(&message, signature, treasure_public_key)
 }
 
+/// The message signed by both the account and treasure keys for a log:
+///
+/// - "log", appended with
+/// - the other key, appended with
+/// - the big-endian u32 length of the comment, appended with
+/// - the comment, appended with
+/// - the hash of the log image, empty if there is none
+fn log_message(other_key: &PublicKey, comment: &str, image_hash: &[u8]) -> Vec<u8> {
+    let mut message = Vec::from("log");
+    message.extend_from_slice(&other_key.to_bytes());
+    message.extend_from_slice(&(comment.len() as u32).to_be_bytes());
+    message.extend_from_slice(comment.as_bytes());
+    message.extend_from_slice(image_hash);
+    message
+}
+
+/// With the account secret key, sign a log of the treasure.
+pub fn sign_log_request_for_account(
+    account_secret_key: &SecretKey,
+    treasure_public_key: &PublicKey,
+    comment: &str,
+    image_hash: &[u8],
+) -> Result<Signature> {
+    let message = log_message(treasure_public_key, comment, image_hash);
+
+    create_signature(&message, account_secret_key)
+}
+
+/// With the account public key, verify
+/// the log request signature.
+pub fn verify_log_request_for_account(
+    account_public_key: &PublicKey,
+    treasure_public_key: &PublicKey,
+    comment: &str,
+    image_hash: &[u8],
+    signature: &Signature,
+) -> Result<()> {
+    let message = log_message(treasure_public_key, comment, image_hash);
+
+    verify_signature(&message, signature, account_public_key)
+}
+
+/// With the treasure secret key, sign a log by the account.
+pub fn sign_log_request_for_treasure(
+    treasure_secret_key: &SecretKey,
+    account_public_key: &PublicKey,
+    comment: &str,
+    image_hash: &[u8],
+) -> Result<Signature> {
+    let message = log_message(account_public_key, comment, image_hash);
+
+    create_signature(&message, treasure_secret_key)
+}
+
+/// With the treasure public key, verify
+/// the log request signature.
+pub fn verify_log_request_for_treasure(
+    treasure_public_key: &PublicKey,
+    account_public_key: &PublicKey,
+    comment: &str,
+    image_hash: &[u8],
+    signature: &Signature,
+) -> Result<()> {
+    let message = log_message(account_public_key, comment, image_hash);
+
+    verify_signature(&message, signature, treasure_public_key)
+}
+
+/// With the account secret key, sign
+///
+/// - "retire", appended with
+/// - the treasure public key
+pub fn sign_retire_request_for_account(
+    account_secret_key: &SecretKey,
+    treasure_public_key: &PublicKey,
+) -> Result<Signature> {
+    let mut message = Vec::from("retire");
+    message.extend_from_slice(&treasure_public_key.to_bytes());
+
+    create_signature(&message, account_secret_key)
+}
+
+/// With the account public key, verify
+/// the retire request signature.
+pub fn verify_retire_request_for_account(
+    account_public_key: &PublicKey,
+    treasure_public_key: &PublicKey,
+    signature: &Signature,
+) -> Result<()> {
+    let mut message = Vec::from("retire");
+    message.extend_from_slice(&treasure_public_key.to_bytes());
+
+    verify_signature(&message, signature, account_public_key)
+}
+
+/// Decode a base58 Solana address.
+pub fn decode_wallet_address(wallet: &str) -> Result<[u8; 32]> {
+    let bytes = bs58::decode(wallet.trim())
+        .into_vec()
+        .map_err(|_| anyhow!("invalid wallet address"))?;
+    if bytes.len() != 32 {
+        bail!("wallet address must be 32 bytes");
+    }
+    let mut address = [0; 32];
+    address.copy_from_slice(&bytes);
+    Ok(address)
+}
+
+/// With the account secret key, sign
+///
+/// - "claim-wallet", appended with
+/// - the treasure public key, appended with
+/// - the wallet address the treasure NFT is minted to
+pub fn sign_claim_wallet_for_account(
+    account_secret_key: &SecretKey,
+    treasure_public_key: &PublicKey,
+    wallet: &[u8; 32],
+) -> Result<Signature> {
+    let mut message = Vec::from("claim-wallet");
+    message.extend_from_slice(&treasure_public_key.to_bytes());
+    message.extend_from_slice(wallet);
+
+    create_signature(&message, account_secret_key)
+}
+
+/// With the account public key, verify
+/// the claim wallet signature.
+pub fn verify_claim_wallet_for_account(
+    account_public_key: &PublicKey,
+    treasure_public_key: &PublicKey,
+    wallet: &[u8; 32],
+    signature: &Signature,
+) -> Result<()> {
+    let mut message = Vec::from("claim-wallet");
+    message.extend_from_slice(&treasure_public_key.to_bytes());
+    message.extend_from_slice(wallet);
+
+    verify_signature(&message, signature, account_public_key)
+}
+
+/// The message a Solana wallet signs to link itself to an account.
+///
+/// It is meant to be read by the player in their wallet's signing prompt.
+pub fn link_wallet_message(account_public_key: &str, timestamp: u64) -> String {
+    format!(
+        "Link this wallet to TreasureTree account {} at {}",
+        account_public_key, timestamp
+    )
+}
+
+/// With the account secret key, sign
+///
+/// - "link-wallet", appended with
+/// - the wallet address, appended with
+/// - the big-endian timestamp
+pub fn sign_link_wallet_for_account(
+    account_secret_key: &SecretKey,
+    wallet: &[u8; 32],
+    timestamp: u64,
+) -> Result<Signature> {
+    let mut message = Vec::from("link-wallet");
+    message.extend_from_slice(wallet);
+    message.extend_from_slice(&timestamp.to_be_bytes());
+
+    create_signature(&message, account_secret_key)
+}
+
+/// With the account public key, verify
+/// the link wallet signature.
+pub fn verify_link_wallet_for_account(
+    account_public_key: &PublicKey,
+    wallet: &[u8; 32],
+    timestamp: u64,
+    signature: &Signature,
+) -> Result<()> {
+    let mut message = Vec::from("link-wallet");
+    message.extend_from_slice(wallet);
+    message.extend_from_slice(&timestamp.to_be_bytes());
+
+    verify_signature(&message, signature, account_public_key)
+}
+
 pub fn encode_signature(sig: &Signature) -> Result<String> {
     let bytes = sig.as_bytes();
     let encoded = base64::encode(bytes);
@@ -365,3 +765,198 @@ impl<T> ResultWrapper<T> for Result<T, k256::ecdsa::Error> {
         self.map_err(|e| anyhow::anyhow!("{}", e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn account_secret_key_from_mnemonic_test_vector() {
+        // The BIP39 test vector mnemonic for all-zero entropy
+        let phrase = "abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon abandon abandon about";
+        let key = account_secret_key_from_mnemonic(phrase).unwrap();
+        assert_eq!(
+            encode_account_secret_key(&key).unwrap(),
+            "gas1cexmye5dfh893as909mjfnf6t4txk86gp9h5qamdd9tneq3jj2hs9msrec"
+        );
+
+        let shouted = "ABANDON ABANDON ABANDON ABANDON ABANDON ABANDON\n\
+                       ABANDON ABANDON ABANDON ABANDON ABANDON ABOUT";
+        let key2 = account_secret_key_from_mnemonic(shouted).unwrap();
+        assert_eq!(key.to_bytes(), key2.to_bytes());
+    }
+
+    #[test]
+    fn derive_treasure_keypair_test_vectors() {
+        let seed =
+            decode_planter_seed("gps1yg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3q5cup3r")
+                .unwrap();
+        assert_eq!(seed, [0x22; 32]);
+
+        let vectors = [
+            (
+                0,
+                "gts14yfrrqn38y9z7sf39yan6wtpxzfmcgw4zuuvmyyhkj0t2d5j8masfkldq7",
+            ),
+            (
+                7,
+                "gts1hwygalwklunza42dezlwdtpgttyscnnlzvvvryd50jl27nk60gkspkyda5",
+            ),
+        ];
+
+        for (index, expected) in vectors.iter() {
+            let keypair = derive_treasure_keypair(&seed, *index).unwrap();
+            assert_eq!(
+                encode_treasure_secret_key(&keypair.secret).unwrap(),
+                *expected
+            );
+        }
+    }
+
+    #[test]
+    fn account_secret_key_from_bad_mnemonic() {
+        // Bad checksum
+        let phrase = "abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon abandon abandon abandon";
+        assert!(account_secret_key_from_mnemonic(phrase).is_err());
+    }
+
+    const TEST_TREASURE_SECRET_KEY: &str =
+        "gts14yfrrqn38y9z7sf39yan6wtpxzfmcgw4zuuvmyyhkj0t2d5j8masfkldq7";
+
+    fn test_treasure_keypair() -> Keypair {
+        keypair_from_treasure_secret_key(TEST_TREASURE_SECRET_KEY).unwrap()
+    }
+
+    #[test]
+    fn claim_url_round_trip() {
+        let keypair = test_treasure_keypair();
+        let url =
+            keypair_to_treasure_secret_claim_url(&keypair, "https://treasuretree.org/").unwrap();
+        assert_eq!(
+            url,
+            "https://treasuretree.org/claim?key=\
+             gts14yfrrqn38y9z7sf39yan6wtpxzfmcgw4zuuvmyyhkj0t2d5j8masfkldq7"
+        );
+
+        let parsed = parse_treasure_secret_url(&url).unwrap();
+        assert_eq!(parsed.origin, "https://treasuretree.org");
+        assert_eq!(parsed.path, TreasureSecretPath::Claim);
+        assert_eq!(parsed.key, TEST_TREASURE_SECRET_KEY);
+    }
+
+    #[test]
+    fn compact_claim_url_round_trip() {
+        let keypair = test_treasure_keypair();
+        let url =
+            keypair_to_treasure_secret_compact_claim_url(&keypair, "https://treasuretree.org")
+                .unwrap();
+        assert_eq!(
+            url,
+            "HTTPS://TREASURETREE.ORG/C/\
+             GTS14YFRRQN38Y9Z7SF39YAN6WTPXZFMCGW4ZUUVMYYHKJ0T2D5J8MASFKLDQ7"
+        );
+
+        let parsed = parse_treasure_secret_url(&url).unwrap();
+        assert_eq!(parsed.path, TreasureSecretPath::CompactClaim);
+        assert!(parsed.is_allowed_origin(&["https://treasuretree.org/"]));
+
+        let decoded = treasure_secret_url_to_keypair(&url).unwrap();
+        assert_eq!(decoded.secret.to_bytes(), keypair.secret.to_bytes());
+    }
+
+    #[test]
+    fn compact_claim_url_lowercase() {
+        // Some scanners lowercase what they read
+        let url = "https://treasuretree.org/c/\
+                   gts14yfrrqn38y9z7sf39yan6wtpxzfmcgw4zuuvmyyhkj0t2d5j8masfkldq7";
+
+        let parsed = parse_treasure_secret_url(url).unwrap();
+        assert_eq!(parsed.path, TreasureSecretPath::CompactClaim);
+        assert!(parsed.is_allowed_origin(&["https://treasuretree.org"]));
+
+        let decoded = treasure_secret_url_to_keypair(url).unwrap();
+        assert_eq!(
+            decoded.secret.to_bytes(),
+            test_treasure_keypair().secret.to_bytes()
+        );
+    }
+
+    #[test]
+    fn treasure_secret_url_unknown_host() {
+        let url = "HTTPS://EXAMPLE.COM/C/\
+                   GTS14YFRRQN38Y9Z7SF39YAN6WTPXZFMCGW4ZUUVMYYHKJ0T2D5J8MASFKLDQ7";
+
+        let parsed = parse_treasure_secret_url(url).unwrap();
+        assert_eq!(parsed.origin, "HTTPS://EXAMPLE.COM");
+        assert!(!parsed.is_allowed_origin(&["https://treasuretree.org"]));
+    }
+
+    #[test]
+    fn treasure_secret_url_truncated_key() {
+        let url = "HTTPS://TREASURETREE.ORG/C/\
+                   GTS14YFRRQN38Y9Z7SF39YAN6WTPXZFMCGW4ZUUVMYYHKJ0T2D5J8MASFKLDQ";
+        assert!(treasure_secret_url_to_keypair(url).is_err());
+
+        let url = "HTTPS://TREASURETREE.ORG/C/";
+        assert!(treasure_secret_url_to_keypair(url).is_err());
+    }
+
+    #[test]
+    fn treasure_secret_url_bad_path() {
+        let url = "https://treasuretree.org/treasure?key=\
+                   gts14yfrrqn38y9z7sf39yan6wtpxzfmcgw4zuuvmyyhkj0t2d5j8masfkldq7";
+        assert!(parse_treasure_secret_url(url).is_err());
+
+        assert!(parse_treasure_secret_url("treasuretree.org/C/GTS1").is_err());
+        assert!(parse_treasure_secret_url("https:///C/GTS1").is_err());
+    }
+
+    #[test]
+    fn log_message_layout() {
+        let key = test_treasure_keypair().public;
+        let message = log_message(&key, "hi", b"hash");
+
+        let mut expected = Vec::from("log");
+        expected.extend_from_slice(&key.to_bytes());
+        // The comment length is big-endian, like the other numbers signed
+        expected.extend_from_slice(&[0, 0, 0, 2]);
+        expected.extend_from_slice(b"hi");
+        expected.extend_from_slice(b"hash");
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn link_wallet_signature() {
+        let phrase = "abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon abandon abandon about";
+        let account = keypair_from_account_mnemonic(phrase).unwrap();
+        let wallet = [7; 32];
+
+        let signature =
+            sign_link_wallet_for_account(&account.secret, &wallet, 1_626_000_000).unwrap();
+        verify_link_wallet_for_account(&account.public, &wallet, 1_626_000_000, &signature)
+            .unwrap();
+
+        assert!(verify_link_wallet_for_account(
+            &account.public,
+            &wallet,
+            1_626_000_001,
+            &signature
+        )
+        .is_err());
+        assert!(verify_link_wallet_for_account(
+            &account.public,
+            &[8; 32],
+            1_626_000_000,
+            &signature
+        )
+        .is_err());
+        let other = test_treasure_keypair();
+        assert!(
+            verify_link_wallet_for_account(&other.public, &wallet, 1_626_000_000, &signature)
+                .is_err()
+        );
+    }
+}
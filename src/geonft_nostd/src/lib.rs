@@ -5,6 +5,7 @@ extern crate alloc;
 use alloc::format;
 use alloc::string::String;
 
+pub mod backup;
 pub mod crypto;
 
 pub fn abbrev_pubkey(key: &str) -> String {
@@ -24,8 +24,61 @@ pub struct PlantRequest {
     /// A base64-encoded signature by the treasure key of
     /// the string "plant",
     /// appended by the encoded account public key,
-    /// appended by the binary sha256 hash of the image.
+    /// appended by the binary sha256 hash of the image,
+    /// appended by `plant_signed_bytes` of the optional fields below.
     pub treasure_signature: String,
+    /// Optional details for treasure hunters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<TreasureDetails>,
+    /// Seconds since the unix epoch before which
+    /// the treasure can't be claimed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<i64>,
+    /// Seconds since the unix epoch after which
+    /// the treasure can't be claimed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<i64>,
+}
+
+/// The bytes covered by the treasure signature of a plant request
+/// after the image hash.
+///
+/// These are empty for a treasure with no details and no claim window,
+/// so plain treasures are signed as before.
+/// Otherwise they are the Borsh encoding of all three fields:
+/// strings are prefixed by their little-endian u32 length,
+/// and options by a 0 or 1 byte for whether they are present,
+/// so no two different requests have the same bytes.
+pub fn plant_signed_bytes(
+    details: &Option<TreasureDetails>,
+    not_before: Option<i64>,
+    not_after: Option<i64>,
+) -> Vec<u8> {
+    if details.is_none() && not_before.is_none() && not_after.is_none() {
+        return Vec::new();
+    }
+
+    (details.clone(), not_before, not_after)
+        .try_to_vec()
+        .expect("serializing to a vec can't fail")
+}
+
+/// Details a planter can attach to a treasure
+#[derive(
+    Debug, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize, Hash, Eq, PartialEq,
+)]
+pub struct TreasureDetails {
+    /// A short title
+    pub title: String,
+    /// A free-text hint for finding the treasure
+    pub hint: String,
+    /// How hard the treasure is to find, 1 to 5
+    pub difficulty: u8,
+    /// How hard the terrain is to get to the treasure, 1 to 5
+    pub terrain: u8,
+    /// An RFC 3339 time after which the treasure may be gone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
 }
 
 /// A Rocket request to claim a treasure
@@ -38,11 +91,95 @@ pub struct ClaimRequest {
     /// A base64-encoded signature by the account key of
     /// the string "claim",
     /// appended by the encoded treasure public key,
+    /// appended by the big-endian timestamp.
     pub account_signature: String,
     /// A base64-encoded signature by the treasure key of
     /// the string "claim",
     /// appended by the encoded account public key.
     pub treasure_signature: String,
+    /// Seconds since the unix epoch when the claim was signed.
+    /// The treasure's claim window is checked at this time,
+    /// both here and on Solana, which may see the claim much later.
+    ///
+    /// Required for new claims,
+    /// but missing from claims recorded before it was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// A base58-encoded Solana address to mint the treasure NFT to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet: Option<String>,
+    /// A base64-encoded signature by the account key of
+    /// the string "claim-wallet",
+    /// appended by the encoded treasure public key,
+    /// appended by the decoded wallet address.
+    /// Required with `wallet`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet_signature: Option<String>,
+}
+
+/// A Rocket request to log finding a treasure,
+/// with a comment and optionally a photo
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub struct LogRequest {
+    /// The public key of the finder's account, bech32 encoded
+    pub account_public_key: String,
+    /// The public key of the treasure, bech32 encoded
+    pub treasure_public_key: String,
+    pub comment: String,
+    /// A photo, base64 encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// A base64-encoded signature by the account key of
+    /// the string "log",
+    /// appended by the encoded treasure public key,
+    /// appended by the big-endian u32 length of the comment,
+    /// appended by the comment,
+    /// appended by the binary sha256 hash of the image, if any.
+    pub account_signature: String,
+    /// A base64-encoded signature by the treasure key of
+    /// the string "log",
+    /// appended by the encoded account public key,
+    /// appended by the comment and image hash as above.
+    pub treasure_signature: String,
+}
+
+/// A Rocket request to retire a treasure,
+/// after which it can't be claimed.
+///
+/// Only the planting account can retire a treasure.
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub struct RetireRequest {
+    /// The public key of the planting account, bech32 encoded
+    pub account_public_key: String,
+    /// The public key of the treasure, bech32 encoded
+    pub treasure_public_key: String,
+    /// A base64-encoded signature by the account key of
+    /// the string "retire",
+    /// appended by the encoded treasure public key.
+    pub account_signature: String,
+}
+
+/// A Rocket request to link a Solana wallet to an account.
+///
+/// Claimed treasures are minted to the claimer's linked wallet
+/// if their claim doesn't name one.
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub struct LinkWalletRequest {
+    /// The public key of the account, bech32 encoded
+    pub account_public_key: String,
+    /// A base58-encoded Solana address
+    pub wallet: String,
+    /// Seconds since the unix epoch when the link was signed.
+    /// A link only replaces an older one.
+    pub timestamp: u64,
+    /// A base64-encoded signature by the account key of
+    /// the string "link-wallet",
+    /// appended by the decoded wallet address,
+    /// appended by the big-endian timestamp.
+    pub account_signature: String,
+    /// A base64-encoded ed25519 signature by the wallet of
+    /// the UTF-8 message from `geonft_nostd::crypto::link_wallet_message`.
+    pub wallet_signature: String,
 }
 
 /// A Solana request
@@ -50,6 +187,10 @@ pub struct ClaimRequest {
 pub enum GeonftRequestSolana {
     PlantTreasure(PlantRequestSolana),
     ClaimTreasure(ClaimRequestSolana),
+    MintTreasure(MintRequestSolana),
+    RetireTreasure(RetireRequestSolana),
+    /// Reserialize a program instance written with an older data layout
+    UpgradeInstance,
 }
 
 /// A Solana request to plant a treasure
@@ -61,6 +202,12 @@ pub struct PlantRequestSolana {
     pub treasure_public_key: Vec<u8>,
     /// An image hash, base64 encoded
     pub treasure_hash: Vec<u8>,
+    /// URL of the treasure's NFT metadata JSON
+    pub metadata_uri: String,
+    /// Unix timestamp before which the treasure can't be claimed
+    pub not_before: Option<i64>,
+    /// Unix timestamp after which the treasure can't be claimed
+    pub not_after: Option<i64>,
 }
 
 /// A Solana request to claim a treasure
@@ -70,4 +217,29 @@ pub struct ClaimRequestSolana {
     pub account_public_key: Vec<u8>,
     /// A public key to represent the treasure, bech32 encoded
     pub treasure_public_key: Vec<u8>,
+    /// The Solana address the treasure NFT may be minted to
+    pub wallet: Option<Vec<u8>>,
+    /// Unix timestamp the claim was signed at,
+    /// used to check the treasure's claim window.
+    /// If missing the window is checked at the current time.
+    pub claimed_at: Option<i64>,
+}
+
+/// A Solana request to mint a claimed treasure as an NFT
+/// to the wallet designated by its claim
+#[derive(Debug, BorshSerialize, BorshDeserialize, Hash, Eq, PartialEq)]
+pub struct MintRequestSolana {
+    /// A public key to represent the treasure, bech32 encoded
+    pub treasure_public_key: Vec<u8>,
+    /// The NFT name
+    pub name: String,
+}
+
+/// A Solana request to retire a treasure
+#[derive(Debug, BorshSerialize, BorshDeserialize, Hash, Eq, PartialEq)]
+pub struct RetireRequestSolana {
+    /// The public key of the planting account
+    pub account_public_key: Vec<u8>,
+    /// A public key to represent the treasure, bech32 encoded
+    pub treasure_public_key: Vec<u8>,
 }
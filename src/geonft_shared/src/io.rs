@@ -1,5 +1,8 @@
-use anyhow::Result;
-use geonft_request::{ClaimRequest, PlantRequest};
+use anyhow::{anyhow, Result};
+use geonft_nostd::crypto;
+use geonft_request::{
+    ClaimRequest, LinkWalletRequest, LogRequest, PlantRequest, RetireRequest, TreasureDetails,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{self, DirEntry, File, Metadata};
@@ -7,7 +10,20 @@ use std::io::{BufReader, BufWriter};
 
 pub static PLANT_DIR: &'static str = "data/plant";
 pub static CLAIM_DIR: &'static str = "data/claim";
+pub static RETIRE_DIR: &'static str = "data/retire";
+/// Finders' logs, at `<treasure public key>/<account public key>`
+pub static LOG_DIR: &'static str = "data/log";
 pub static SYNC_STATUS_DIR: &'static str = "data/sync-status";
+/// Index of plants by account,
+/// an empty file at `<account public key>/<treasure public key>`
+pub static ACCOUNT_PLANTS_DIR: &'static str = "data/account-plants";
+/// Index of claims by account, laid out like `ACCOUNT_PLANTS_DIR`
+pub static ACCOUNT_CLAIMS_DIR: &'static str = "data/account-claims";
+/// The latest wallet link of each account, by account public key
+pub static WALLET_DIR: &'static str = "data/wallet";
+/// The wallet linked to the claimer when a treasure was claimed,
+/// by treasure public key
+pub static CLAIM_WALLET_DIR: &'static str = "data/claim-wallet";
 
 /// A treasure key and the time its record was created,
 /// can be used for both plants and claims.
@@ -24,6 +40,10 @@ pub fn get_all_claimed_treasures() -> Result<Vec<TreasureTime>> {
     get_all_treasures_from_dir(CLAIM_DIR)
 }
 
+pub fn get_all_retired_treasures() -> Result<Vec<TreasureTime>> {
+    get_all_treasures_from_dir(RETIRE_DIR)
+}
+
 fn get_all_treasures_from_dir(dir: &str) -> Result<Vec<TreasureTime>> {
     fs::create_dir_all(dir)?;
     let files = fs::read_dir(dir)?
@@ -72,22 +92,51 @@ pub fn get_all_claimed_treasures_time_sorted() -> Result<Vec<TreasureTime>> {
 pub enum PlantClaim {
     Plant,
     Claim,
+    /// The planter retired the treasure
+    Retire,
 }
 
+/// Every plant, claim and retirement, oldest first.
 pub fn get_all_plants_and_claims_time_sorted() -> Result<Vec<(PlantClaim, TreasureTime)>> {
     let plants = get_all_planted_treasures_time_sorted()?;
     let claims = get_all_claimed_treasures_time_sorted()?;
+    let retires = get_all_retired_treasures()?;
 
     let plants = plants.into_iter().map(|t| (PlantClaim::Plant, t));
     let claims = claims.into_iter().map(|t| (PlantClaim::Claim, t));
+    let retires = retires.into_iter().map(|t| (PlantClaim::Retire, t));
 
-    let mut treasure_events: Vec<_> = plants.chain(claims).collect();
+    let mut treasure_events: Vec<_> = plants.chain(claims).chain(retires).collect();
 
     treasure_events.sort_by_key(|(_, t)| t.time);
 
     Ok(treasure_events)
 }
 
+/// The plant, claim and retire records that exist for a single treasure,
+/// in the order they must be synced.
+pub fn get_treasure_events(key: &str) -> Vec<PlantClaim> {
+    let plant_path = format!("{}/{}", PLANT_DIR, key);
+    let claim_path = format!("{}/{}", CLAIM_DIR, key);
+    let retire_path = format!("{}/{}", RETIRE_DIR, key);
+
+    let mut events = Vec::new();
+
+    if fs::metadata(plant_path).is_ok() {
+        events.push(PlantClaim::Plant);
+    }
+
+    if fs::metadata(claim_path).is_ok() {
+        events.push(PlantClaim::Claim);
+    }
+
+    if fs::metadata(retire_path).is_ok() {
+        events.push(PlantClaim::Retire);
+    }
+
+    events
+}
+
 pub fn get_plant(key: &str) -> Result<PlantRequest> {
     fs::create_dir_all(PLANT_DIR)?;
 
@@ -110,11 +159,361 @@ pub fn get_claim(key: &str) -> Result<ClaimRequest> {
     Ok(req)
 }
 
+pub fn get_retire(key: &str) -> Result<RetireRequest> {
+    fs::create_dir_all(RETIRE_DIR)?;
+
+    let path = format!("{}/{}", RETIRE_DIR, key);
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let req = serde_json::from_reader(reader)?;
+
+    Ok(req)
+}
+
+pub fn get_log(treasure_key: &str, account_key: &str) -> Result<LogRequest> {
+    let path = format!("{}/{}/{}", LOG_DIR, treasure_key, account_key);
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let req = serde_json::from_reader(reader)?;
+
+    Ok(req)
+}
+
+pub fn has_log(treasure_key: &str, account_key: &str) -> bool {
+    let path = format!("{}/{}/{}", LOG_DIR, treasure_key, account_key);
+    fs::metadata(path).is_ok()
+}
+
+pub fn record_log(treasure_key: &str, account_key: &str, log: &LogRequest) -> Result<()> {
+    let dir = format!("{}/{}", LOG_DIR, treasure_key);
+    fs::create_dir_all(&dir)?;
+
+    let path = format!("{}/{}", dir, account_key);
+    let file = File::create(path)?;
+    let writer = BufWriter::new(file);
+    serde_json::to_writer(writer, log)?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct LogTemplateData {
+    pub account_public_key: String,
+    pub account_url: String,
+    pub logged_date_time: String,
+    pub comment: String,
+    pub image_url: Option<String>,
+}
+
+/// The logs of a treasure, newest first.
+pub fn load_treasure_logs(treasure_key: &str) -> Result<Vec<LogTemplateData>> {
+    let dir = format!("{}/{}", LOG_DIR, treasure_key);
+    if fs::metadata(&dir).is_err() {
+        return Ok(Vec::new());
+    }
+
+    let mut logs = get_all_treasures_from_dir(&dir)?;
+    logs.sort_by(|a, b| b.time.cmp(&a.time));
+
+    logs.into_iter()
+        .map(|log_time| {
+            let account_key = log_time.public_key;
+            let log = get_log(treasure_key, &account_key)?;
+            Ok(LogTemplateData {
+                account_url: format!("account/{}", account_key),
+                logged_date_time: log_time.time.to_rfc2822(),
+                comment: log.comment,
+                image_url: log
+                    .image
+                    .map(|_| format!("log-images/{}/{}", treasure_key, account_key)),
+                account_public_key: account_key,
+            })
+        })
+        .collect()
+}
+
+pub fn is_retired(key: &str) -> bool {
+    let path = format!("{}/{}", RETIRE_DIR, key);
+    fs::metadata(path).is_ok()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClaimStatus {
+    Claimed,
+    Unclaimed,
+}
+
+/// A query for planted treasures, newest first,
+/// used by both the `/recent` page and the JSON API.
+#[derive(Debug, Clone)]
+pub struct TreasureQuery {
+    /// Continue after the last treasure of a previous page
+    pub cursor: Option<TreasureCursor>,
+    pub limit: usize,
+    pub status: Option<ClaimStatus>,
+    /// A normalized account public key
+    pub planted_by: Option<String>,
+    /// Only treasures planted at or after this time
+    pub since: Option<chrono::DateTime<chrono::Local>>,
+}
+
+/// The position of a treasure in the listing.
+///
+/// Treasures are ordered by plant time then public key,
+/// so the position of a treasure never changes.
+/// Formatted as `<plant time millis>-<public key>`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TreasureCursor {
+    pub time_millis: i64,
+    pub public_key: String,
+}
+
+impl TreasureCursor {
+    fn of(treasure: &TreasureTime) -> TreasureCursor {
+        TreasureCursor {
+            time_millis: treasure.time.timestamp_millis(),
+            public_key: treasure.public_key.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for TreasureCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", self.time_millis, self.public_key)
+    }
+}
+
+impl std::str::FromStr for TreasureCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<TreasureCursor> {
+        let mut parts = s.splitn(2, '-');
+        let time_millis = parts.next().unwrap_or("").parse()?;
+        let public_key = parts
+            .next()
+            .ok_or_else(|| anyhow!("invalid treasure cursor"))?
+            .to_string();
+        Ok(TreasureCursor {
+            time_millis,
+            public_key,
+        })
+    }
+}
+
+pub struct TreasurePage {
+    pub treasures: Vec<TreasureTime>,
+    /// The cursor for the next page, if there may be one
+    pub next_cursor: Option<TreasureCursor>,
+}
+
+pub fn query_treasures(query: &TreasureQuery) -> Result<TreasurePage> {
+    let treasures = match &query.planted_by {
+        Some(account_key) => get_account_treasures(account_key)?.planted,
+        None => get_all_planted_treasures()?,
+    };
+
+    let mut treasures: Vec<(TreasureCursor, TreasureTime)> = treasures
+        .into_iter()
+        .filter(|t| query.since.map(|since| t.time >= since).unwrap_or(true))
+        .map(|t| (TreasureCursor::of(&t), t))
+        .filter(|(cursor, _)| {
+            query
+                .cursor
+                .as_ref()
+                .map(|after| cursor < after)
+                .unwrap_or(true)
+        })
+        .collect();
+
+    // Newest first
+    treasures.sort_by(|(c1, _), (c2, _)| c2.cmp(c1));
+
+    let mut page = Vec::new();
+    let mut next_cursor = None;
+
+    for (cursor, treasure) in treasures {
+        if let Some(status) = query.status {
+            let claim_path = format!("{}/{}", CLAIM_DIR, treasure.public_key);
+            let claimed = fs::metadata(claim_path).is_ok();
+            if claimed != (status == ClaimStatus::Claimed) {
+                continue;
+            }
+        }
+
+        page.push(treasure);
+
+        if page.len() == query.limit {
+            next_cursor = Some(cursor);
+            break;
+        }
+    }
+
+    Ok(TreasurePage {
+        treasures: page,
+        next_cursor,
+    })
+}
+
+pub fn record_account_plant(account_key: &str, treasure_key: &str) -> Result<()> {
+    record_account_treasure(ACCOUNT_PLANTS_DIR, account_key, treasure_key)
+}
+
+pub fn record_account_claim(account_key: &str, treasure_key: &str) -> Result<()> {
+    record_account_treasure(ACCOUNT_CLAIMS_DIR, account_key, treasure_key)
+}
+
+fn record_account_treasure(index_dir: &str, account_key: &str, treasure_key: &str) -> Result<()> {
+    let dir = format!("{}/{}", index_dir, account_key);
+    fs::create_dir_all(&dir)?;
+
+    let path = format!("{}/{}", dir, treasure_key);
+    File::create(path)?;
+
+    Ok(())
+}
+
+pub fn get_account_wallet(account_key: &str) -> Result<Option<LinkWalletRequest>> {
+    let path = format!("{}/{}", WALLET_DIR, account_key);
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let reader = BufReader::new(file);
+    let req = serde_json::from_reader(reader)?;
+
+    Ok(Some(req))
+}
+
+pub fn record_account_wallet(account_key: &str, link: &LinkWalletRequest) -> Result<()> {
+    fs::create_dir_all(WALLET_DIR)?;
+
+    let path = format!("{}/{}", WALLET_DIR, account_key);
+    let file = File::create(path)?;
+    let writer = BufWriter::new(file);
+    serde_json::to_writer(writer, link)?;
+
+    Ok(())
+}
+
+/// Remember the wallet linked to the claimer at the time of a claim,
+/// so the claim is synced and minted to the same wallet
+/// even if the account links another one later.
+pub fn record_claim_wallet(treasure_key: &str, wallet: &str) -> Result<()> {
+    fs::create_dir_all(CLAIM_WALLET_DIR)?;
+
+    let path = format!("{}/{}", CLAIM_WALLET_DIR, treasure_key);
+    fs::write(path, wallet)?;
+
+    Ok(())
+}
+
+/// The wallet a claimed treasure is minted to:
+/// the one named by the claim,
+/// or else the one linked to the claimer when it was claimed.
+pub fn get_claim_wallet(treasure_key: &str) -> Result<Option<String>> {
+    let claim = get_claim(treasure_key)?;
+    if claim.wallet.is_some() {
+        return Ok(claim.wallet);
+    }
+
+    let path = format!("{}/{}", CLAIM_WALLET_DIR, treasure_key);
+    match fs::read_to_string(path) {
+        Ok(wallet) => Ok(Some(wallet)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The treasures planted and claimed by an account, newest first.
+///
+/// Times are those of the plant and claim records,
+/// not of the index entries.
+pub struct AccountTreasures {
+    pub planted: Vec<TreasureTime>,
+    pub claimed: Vec<TreasureTime>,
+}
+
+pub fn get_account_treasures(account_key: &str) -> Result<AccountTreasures> {
+    Ok(AccountTreasures {
+        planted: get_account_treasures_from_index(ACCOUNT_PLANTS_DIR, PLANT_DIR, account_key)?,
+        claimed: get_account_treasures_from_index(ACCOUNT_CLAIMS_DIR, CLAIM_DIR, account_key)?,
+    })
+}
+
+fn get_account_treasures_from_index(
+    index_dir: &str,
+    record_dir: &str,
+    account_key: &str,
+) -> Result<Vec<TreasureTime>> {
+    let dir = format!("{}/{}", index_dir, account_key);
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        // An account with no treasures has no index
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut treasures = Vec::new();
+
+    for dent in entries {
+        let public_key = dent?
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("non-UTF-8 file name {:?} in {}", name, dir))?;
+        let record_path = format!("{}/{}", record_dir, public_key);
+        let metadata = match fs::metadata(record_path) {
+            Ok(metadata) => metadata,
+            // Not a treasure we have a record of
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let time = metadata.modified()?;
+        let time = chrono::DateTime::<chrono::Local>::from(time);
+        treasures.push(TreasureTime { public_key, time });
+    }
+
+    treasures.sort_by(|t1, t2| t2.time.cmp(&t1.time));
+
+    Ok(treasures)
+}
+
+/// Add any plants and claims missing from the by-account index,
+/// e.g. those recorded before the index existed.
+pub fn rebuild_account_index() -> Result<()> {
+    for treasure in get_all_planted_treasures()? {
+        let plant = get_plant(&treasure.public_key)?;
+        let account_key = normalize_account_key(&plant.account_public_key)?;
+        record_account_plant(&account_key, &treasure.public_key)?;
+    }
+
+    for treasure in get_all_claimed_treasures()? {
+        let claim = get_claim(&treasure.public_key)?;
+        let account_key = normalize_account_key(&claim.account_public_key)?;
+        record_account_claim(&account_key, &treasure.public_key)?;
+    }
+
+    Ok(())
+}
+
+/// bech32 decoding accepts uppercase,
+/// so re-encode account keys before using them as file names.
+pub fn normalize_account_key(account_key: &str) -> Result<String> {
+    let key = crypto::decode_account_public_key(account_key)?;
+    crypto::encode_account_public_key(&key)
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
 pub enum SyncStatus {
     BlobSynced,
     PlantSynced,
     ClaimSynced,
+    /// The claimed treasure was minted to the claimer's wallet
+    MintSynced,
+    /// The treasure was retired by its planter,
+    /// after any plant, claim and mint were synced
+    RetireSynced,
 }
 
 pub fn get_all_sync_statuses() -> Result<HashMap<String, SyncStatus>> {
@@ -144,7 +543,7 @@ pub fn get_all_sync_statuses() -> Result<HashMap<String, SyncStatus>> {
     Ok(statuses)
 }
 
-fn get_sync_status(key: &str) -> Result<SyncStatus> {
+pub fn get_sync_status(key: &str) -> Result<SyncStatus> {
     let path = format!("{}/{}", SYNC_STATUS_DIR, key);
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -173,9 +572,24 @@ pub struct TreasureTemplateData {
     pub image_url: String,
     pub planted_date_time: String,
     pub planted_by: String,
+    pub planted_by_url: String,
     pub claimed_date_time: String,
     pub claimed_by: String,
+    pub claimed_by_url: Option<String>,
+    pub retired_date_time: Option<String>,
     pub sync_status: String,
+    pub details: Option<TreasureDetails>,
+    /// The details' expiry has passed
+    pub expired: bool,
+    /// When the treasure becomes claimable, if it is scheduled
+    pub claimable_from: Option<String>,
+    /// When the treasure stops being claimable, if it is time-limited
+    pub claimable_until: Option<String>,
+    /// How long until the treasure becomes claimable, if it isn't yet
+    pub opens_in: Option<String>,
+    /// How long is left to claim the treasure, if it is time-limited
+    /// and hasn't closed
+    pub closes_in: Option<String>,
 }
 
 pub fn load_treasure_data(public_key: &str) -> Result<TreasureTemplateData> {
@@ -194,10 +608,30 @@ pub fn load_treasure_data(public_key: &str) -> Result<TreasureTemplateData> {
     let plant_reader = BufReader::new(plant_file);
     let plant_request: PlantRequest = serde_json::from_reader(plant_reader)?;
     let planted_by = plant_request.account_public_key;
+    let details = plant_request.details;
+    let expired = details
+        .as_ref()
+        .and_then(|details| details.expires.as_ref())
+        .and_then(|expires| chrono::DateTime::parse_from_rfc3339(expires).ok())
+        .map(|expires| expires < chrono::Local::now())
+        .unwrap_or(false);
+    let now = chrono::Utc::now().timestamp();
+    let claimable_from = plant_request.not_before.and_then(timestamp_date_time);
+    let claimable_until = plant_request.not_after.and_then(timestamp_date_time);
+    let opens_in = plant_request
+        .not_before
+        .filter(|not_before| now < *not_before)
+        .map(|not_before| format_countdown(not_before - now));
+    let closes_in = plant_request
+        .not_after
+        .filter(|not_after| now <= *not_after)
+        .map(|not_after| format_countdown(not_after - now));
+    let planted_by_url = format!("account/{}", planted_by);
 
     let claim_path = format!("{}/{}", CLAIM_DIR, public_key);
     let claimed_date_time;
     let claimed_by;
+    let claimed_by_url;
     if let Ok(claim_file) = File::open(claim_path) {
         let claim_meta = claim_file.metadata()?;
         let claim_time = claim_meta.modified()?;
@@ -206,11 +640,23 @@ pub fn load_treasure_data(public_key: &str) -> Result<TreasureTemplateData> {
         let claim_reader = BufReader::new(claim_file);
         let claim_request: ClaimRequest = serde_json::from_reader(claim_reader)?;
         claimed_by = claim_request.account_public_key;
+        claimed_by_url = Some(format!("account/{}", claimed_by));
     } else {
         claimed_date_time = "unclaimed".to_string();
         claimed_by = "unclaimed".to_string();
+        claimed_by_url = None;
     }
 
+    let retire_path = format!("{}/{}", RETIRE_DIR, public_key);
+    let retired_date_time = match fs::metadata(retire_path) {
+        Ok(retire_meta) => {
+            let retire_time = retire_meta.modified()?;
+            let retire_date_time = chrono::DateTime::<chrono::Local>::from(retire_time);
+            Some(retire_date_time.to_rfc2822())
+        }
+        Err(_) => None,
+    };
+
     let sync_status = get_ui_sync_status(&public_key)?;
 
     Ok(TreasureTemplateData {
@@ -220,12 +666,45 @@ pub fn load_treasure_data(public_key: &str) -> Result<TreasureTemplateData> {
         image_url,
         planted_date_time,
         planted_by,
+        planted_by_url,
         claimed_date_time,
         claimed_by,
+        claimed_by_url,
+        retired_date_time,
         sync_status,
+        details,
+        expired,
+        claimable_from,
+        claimable_until,
+        opens_in,
+        closes_in,
     })
 }
 
+/// A unix timestamp in the format of other template dates
+fn timestamp_date_time(timestamp: i64) -> Option<String> {
+    use chrono::TimeZone;
+
+    chrono::Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date_time| date_time.to_rfc2822())
+}
+
+/// A countdown like "2d 5h 13m", to the minute
+fn format_countdown(seconds: i64) -> String {
+    let minutes = (seconds + 59) / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
 fn get_ui_sync_status(public_key: &str) -> Result<String> {
     let plant_path = format!("{}/{}", PLANT_DIR, public_key);
     let claim_path = format!("{}/{}", CLAIM_DIR, public_key);
@@ -233,15 +712,203 @@ fn get_ui_sync_status(public_key: &str) -> Result<String> {
     let have_claim = fs::metadata(claim_path).is_ok();
     let sync_status = get_sync_status(public_key).ok();
 
+    if is_retired(public_key) {
+        return Ok(match sync_status {
+            Some(SyncStatus::RetireSynced) => "synced",
+            _ => "unsynced",
+        }
+        .to_string());
+    }
+
     Ok(match (have_plant, have_claim, sync_status) {
         (false, _, _) => unreachable!(),
+        // The retirement record is gone, so it will be synced again
+        (true, _, Some(SyncStatus::RetireSynced)) => "unsynced",
         (true, false, None | Some(SyncStatus::BlobSynced)) => "unsynced",
         (true, false, Some(SyncStatus::PlantSynced)) => "synced",
-        (true, false, Some(SyncStatus::ClaimSynced)) => unreachable!(),
+        (true, false, Some(SyncStatus::ClaimSynced | SyncStatus::MintSynced)) => unreachable!(),
         (true, true, None | Some(SyncStatus::BlobSynced) | Some(SyncStatus::PlantSynced)) => {
             "unsynced"
         }
-        (true, true, Some(SyncStatus::ClaimSynced)) => "synced",
+        (true, true, Some(SyncStatus::ClaimSynced | SyncStatus::MintSynced)) => "synced",
     }
     .to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use filetime::FileTime;
+    use std::sync::{Mutex, MutexGuard, Once};
+
+    /// Change to an empty data directory.
+    ///
+    /// Records are read from `data/` under the working directory,
+    /// so tests take turns with it.
+    fn empty_data_dir() -> MutexGuard<'static, ()> {
+        static INIT: Once = Once::new();
+        lazy_static::lazy_static! {
+            static ref LOCK: Mutex<()> = Mutex::new(());
+        }
+
+        INIT.call_once(|| {
+            let dir =
+                std::env::temp_dir().join(format!("geonft-shared-test-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            std::env::set_current_dir(&dir).unwrap();
+        });
+
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = fs::remove_dir_all("data");
+        guard
+    }
+
+    /// Write an empty record modified at `millis`.
+    fn write_record(dir: &str, key: &str, millis: i64) {
+        fs::create_dir_all(dir).unwrap();
+        let path = format!("{}/{}", dir, key);
+        File::create(&path).unwrap();
+        let time = FileTime::from_unix_time(millis / 1000, (millis % 1000) as u32 * 1_000_000);
+        filetime::set_file_mtime(&path, time).unwrap();
+    }
+
+    fn query(limit: usize) -> TreasureQuery {
+        TreasureQuery {
+            cursor: None,
+            limit,
+            status: None,
+            planted_by: None,
+            since: None,
+        }
+    }
+
+    fn keys(page: &TreasurePage) -> Vec<&str> {
+        page.treasures
+            .iter()
+            .map(|t| t.public_key.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = TreasureCursor {
+            time_millis: 1_600_000_000_123,
+            public_key: "gtp1-with-dash".to_string(),
+        };
+        let formatted = cursor.to_string();
+        assert_eq!(formatted, "1600000000123-gtp1-with-dash");
+        assert_eq!(formatted.parse::<TreasureCursor>().unwrap(), cursor);
+
+        assert!("".parse::<TreasureCursor>().is_err());
+        assert!("1600000000123".parse::<TreasureCursor>().is_err());
+        assert!("gtp1-1600000000123".parse::<TreasureCursor>().is_err());
+    }
+
+    #[test]
+    fn pages_newest_first() {
+        let _data_dir = empty_data_dir();
+        write_record(PLANT_DIR, "a", 1_000);
+        write_record(PLANT_DIR, "b", 3_000);
+        write_record(PLANT_DIR, "c", 2_000);
+
+        let mut query = query(2);
+        let page = query_treasures(&query).unwrap();
+        assert_eq!(keys(&page), vec!["b", "c"]);
+
+        query.cursor = page.next_cursor;
+        let page = query_treasures(&query).unwrap();
+        assert_eq!(keys(&page), vec!["a"]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn pages_stable_within_a_millisecond() {
+        let _data_dir = empty_data_dir();
+        for key in &["d", "b", "e", "a", "c"] {
+            write_record(PLANT_DIR, key, 1_000);
+        }
+
+        let mut query = query(2);
+        let mut listed = Vec::new();
+        loop {
+            let page = query_treasures(&query).unwrap();
+            listed.extend(keys(&page).into_iter().map(str::to_string));
+            // A treasure planted after the listing began is not listed
+            write_record(PLANT_DIR, &format!("f{}", listed.len()), 2_000);
+
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor.to_string().parse().unwrap()),
+                None => break,
+            }
+        }
+
+        assert_eq!(listed, vec!["e", "d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn filter_by_status() {
+        let _data_dir = empty_data_dir();
+        write_record(PLANT_DIR, "a", 1_000);
+        write_record(PLANT_DIR, "b", 2_000);
+        write_record(PLANT_DIR, "c", 3_000);
+        write_record(CLAIM_DIR, "b", 4_000);
+
+        let mut query = query(10);
+        query.status = Some(ClaimStatus::Claimed);
+        assert_eq!(keys(&query_treasures(&query).unwrap()), vec!["b"]);
+
+        query.status = Some(ClaimStatus::Unclaimed);
+        query.limit = 1;
+        let page = query_treasures(&query).unwrap();
+        assert_eq!(keys(&page), vec!["c"]);
+
+        // Treasures filtered out don't end a page
+        query.cursor = page.next_cursor;
+        assert_eq!(keys(&query_treasures(&query).unwrap()), vec!["a"]);
+    }
+
+    #[test]
+    fn filter_by_planter() {
+        let _data_dir = empty_data_dir();
+        write_record(PLANT_DIR, "a", 1_000);
+        write_record(PLANT_DIR, "b", 2_000);
+        write_record(PLANT_DIR, "c", 3_000);
+        record_account_plant("alice", "a").unwrap();
+        record_account_plant("alice", "c").unwrap();
+        record_account_plant("bob", "b").unwrap();
+        // Indexed, but with no plant record
+        record_account_plant("alice", "d").unwrap();
+
+        let mut query = query(10);
+        query.planted_by = Some("alice".to_string());
+        assert_eq!(keys(&query_treasures(&query).unwrap()), vec!["c", "a"]);
+
+        query.planted_by = Some("carol".to_string());
+        assert!(query_treasures(&query).unwrap().treasures.is_empty());
+    }
+
+    #[test]
+    fn ui_sync_status_retire_record_removed() {
+        let _data_dir = empty_data_dir();
+        write_record(PLANT_DIR, "a", 1_000);
+        record_sync_status("a", SyncStatus::RetireSynced).unwrap();
+
+        assert_eq!(get_ui_sync_status("a").unwrap(), "unsynced");
+
+        write_record(RETIRE_DIR, "a", 2_000);
+        assert_eq!(get_ui_sync_status("a").unwrap(), "synced");
+    }
+
+    #[test]
+    fn filter_by_since() {
+        let _data_dir = empty_data_dir();
+        write_record(PLANT_DIR, "a", 1_000);
+        write_record(PLANT_DIR, "b", 2_000);
+        write_record(PLANT_DIR, "c", 3_000);
+
+        let mut query = query(10);
+        query.since = Some(chrono::Local.timestamp_millis(2_000));
+        assert_eq!(keys(&query_treasures(&query).unwrap()), vec!["c", "b"]);
+    }
+}
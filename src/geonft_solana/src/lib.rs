@@ -1,19 +1,58 @@
 use anyhow::anyhow;
 use borsh::{BorshDeserialize, BorshSerialize};
-use geonft_request::{ClaimRequestSolana, GeonftRequestSolana, PlantRequestSolana};
+use geonft_request::{
+    ClaimRequestSolana, GeonftRequestSolana, MintRequestSolana, PlantRequestSolana,
+    RetireRequestSolana,
+};
 use solana_program::borsh::try_from_slice_unchecked;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
+    clock::Clock,
     entrypoint::ProgramResult,
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::{self, Sysvar},
 };
+use spl_token::instruction::AuthorityType;
 use std::collections::BTreeMap;
 
+pub mod metadata;
+mod upgrade;
+
+/// The first byte of a program instance account
+/// is the version of the layout of the data after it.
+pub const UNINITIALIZED_LAYOUT: u8 = 0;
+/// Plants and claims with only their account keys and treasure hash
+const LAYOUT_V1: u8 = 1;
+/// Adds metadata URIs, claim wallets and mints, retirement and claim windows
+const LAYOUT_V2: u8 = 2;
+pub const CURRENT_LAYOUT: u8 = LAYOUT_V2;
+
+/// The longest metadata URI a plant may record,
+/// the same limit as Metaplex token metadata
+const MAX_METADATA_URI_LEN: usize = 200;
+
+/// The symbol of treasure NFTs
+pub static TREASURE_SYMBOL: &str = "TREASURE";
+
+static MINT_SEED: &[u8] = b"treasure-mint";
+
+/// The longest a claim may take to reach the program after it was made.
+///
+/// The claim time isn't signed by anyone the program can check,
+/// so this bounds how far an old claim time can stretch a claim window.
+pub const MAX_CLAIM_DELAY: i64 = 24 * 60 * 60;
+
 // Declare and export the program's entrypoint
-entrypoint!(process_instruction);
+#[cfg(not(feature = "exclude_entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 // Program entrypoint's implementation
 pub fn process_instruction(
@@ -23,6 +62,7 @@ pub fn process_instruction(
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     let account = next_account_info(accounts_iter)?;
+    let other_accounts = accounts_iter.as_slice();
 
     // The account must be owned by the program in order to modify its data
     if account.owner != program_id {
@@ -32,7 +72,10 @@ pub fn process_instruction(
 
     msg!("Geonft_solana entrypoint");
 
-    if account.data.borrow()[0] == 0 {
+    let geonft_request = GeonftRequestSolana::try_from_slice(geonft_request)?;
+
+    let layout = account.data.borrow()[0];
+    if layout == UNINITIALIZED_LAYOUT {
         msg!("init starts");
         let init_treasure = Treasure {
             plant_treasure: BTreeMap::new(),
@@ -40,12 +83,22 @@ pub fn process_instruction(
         };
 
         init_treasure.serialize(&mut &mut account.data.borrow_mut()[1..])?;
-        account.data.borrow_mut()[0] = 1;
+        account.data.borrow_mut()[0] = CURRENT_LAYOUT;
+    } else if layout != CURRENT_LAYOUT {
+        return match geonft_request {
+            GeonftRequestSolana::UpgradeInstance => Ok(upgrade::upgrade_instance(account, layout)?),
+            _ => {
+                msg!(
+                    "Program instance has layout {} and must be upgraded",
+                    layout
+                );
+                Err(ProgramError::InvalidAccountData)
+            }
+        };
     }
 
     let mut treasure_data = try_from_slice_unchecked(&account.data.borrow()[1..])?;
 
-    let geonft_request = GeonftRequestSolana::try_from_slice(geonft_request)?;
     match geonft_request {
         GeonftRequestSolana::PlantTreasure(plant_info) => {
             plant_treasure(plant_info, &mut treasure_data)?;
@@ -53,6 +106,21 @@ pub fn process_instruction(
         GeonftRequestSolana::ClaimTreasure(claim_info) => {
             claim_treasure(claim_info, &mut treasure_data)?;
         }
+        GeonftRequestSolana::MintTreasure(mint_info) => {
+            mint_treasure(
+                program_id,
+                account.key,
+                other_accounts,
+                mint_info,
+                &mut treasure_data,
+            )?;
+        }
+        GeonftRequestSolana::RetireTreasure(retire_info) => {
+            retire_treasure(retire_info, &mut treasure_data)?;
+        }
+        GeonftRequestSolana::UpgradeInstance => {
+            msg!("Program instance already has the current layout");
+        }
     }
 
     Ok(treasure_data.serialize(&mut &mut account.data.borrow_mut()[1..])?)
@@ -68,11 +136,22 @@ pub struct Treasure {
 pub struct PlantTreasure {
     account_pubkey: Vec<u8>,
     treasure_hash: Vec<u8>,
+    metadata_uri: String,
+    /// Retired treasures can't be claimed
+    retired: bool,
+    /// Unix timestamp before which the treasure can't be claimed
+    not_before: Option<i64>,
+    /// Unix timestamp after which the treasure can't be claimed
+    not_after: Option<i64>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Debug)]
 pub struct ClaimTreasure {
     account_pubkey: Vec<u8>,
+    /// The wallet the treasure NFT may be minted to
+    wallet: Option<Vec<u8>>,
+    /// The treasure's mint, once minted
+    mint: Option<Vec<u8>>,
 }
 
 pub fn plant_treasure(
@@ -81,11 +160,27 @@ pub fn plant_treasure(
 ) -> Result<(), GeonftError> {
     msg!("plant_treasure");
 
+    if plant_info.metadata_uri.len() > MAX_METADATA_URI_LEN {
+        return Err(GeonftError::AnyhowError(anyhow!("Metadata URI too long")));
+    }
+
+    if let (Some(not_before), Some(not_after)) = (plant_info.not_before, plant_info.not_after) {
+        if not_before >= not_after {
+            return Err(GeonftError::AnyhowError(anyhow!(
+                "Claim window must start before it ends"
+            )));
+        }
+    }
+
     treasure_data.plant_treasure.insert(
         plant_info.treasure_public_key.to_vec(),
         PlantTreasure {
             account_pubkey: plant_info.account_public_key,
             treasure_hash: plant_info.treasure_hash,
+            metadata_uri: plant_info.metadata_uri,
+            retired: false,
+            not_before: plant_info.not_before,
+            not_after: plant_info.not_after,
         },
     );
 
@@ -100,13 +195,49 @@ pub fn claim_treasure(
 
     let treasure_pubkey = &claim_info.treasure_public_key;
 
-    if !treasure_data.plant_treasure.contains_key(treasure_pubkey) {
-        Err(GeonftError::AnyhowError(anyhow!("Treasure doesn't exist")))
+    let plant = match treasure_data.plant_treasure.get(treasure_pubkey) {
+        Some(plant) => plant,
+        None => return Err(GeonftError::AnyhowError(anyhow!("Treasure doesn't exist"))),
+    };
+
+    // Claims reach the program some time after they are made,
+    // so the window is checked at the time the claimer signed.
+    let now = Clock::get()?.unix_timestamp;
+    let claimed_at = claim_info.claimed_at.unwrap_or(now);
+
+    if plant.retired {
+        Err(GeonftError::AnyhowError(anyhow!("Treasure is retired")))
+    } else if treasure_data.claim_treasure.contains_key(treasure_pubkey) {
+        Err(GeonftError::AnyhowError(anyhow!(
+            "Treasure already claimed"
+        )))
+    } else if claimed_at > now {
+        Err(GeonftError::AnyhowError(anyhow!(
+            "Claim time is in the future"
+        )))
+    } else if now - claimed_at > MAX_CLAIM_DELAY {
+        Err(GeonftError::AnyhowError(anyhow!("Claim is too old")))
+    } else if plant
+        .not_before
+        .map_or(false, |not_before| claimed_at < not_before)
+    {
+        Err(GeonftError::AnyhowError(anyhow!(
+            "Treasure can't be claimed yet"
+        )))
+    } else if plant
+        .not_after
+        .map_or(false, |not_after| claimed_at > not_after)
+    {
+        Err(GeonftError::AnyhowError(anyhow!(
+            "Treasure can no longer be claimed"
+        )))
     } else {
         treasure_data.claim_treasure.insert(
             treasure_pubkey.to_vec(),
             ClaimTreasure {
                 account_pubkey: claim_info.account_public_key,
+                wallet: claim_info.wallet,
+                mint: None,
             },
         );
 
@@ -114,6 +245,304 @@ pub fn claim_treasure(
     }
 }
 
+/// Retire a treasure at the request of its planter.
+pub fn retire_treasure(
+    retire_info: RetireRequestSolana,
+    treasure_data: &mut Treasure,
+) -> Result<(), GeonftError> {
+    msg!("retire_treasure");
+
+    let plant = match treasure_data
+        .plant_treasure
+        .get_mut(&retire_info.treasure_public_key)
+    {
+        Some(plant) => plant,
+        None => return Err(GeonftError::AnyhowError(anyhow!("Treasure doesn't exist"))),
+    };
+
+    if plant.account_pubkey != retire_info.account_public_key {
+        return Err(GeonftError::AnyhowError(anyhow!(
+            "Only the planter can retire a treasure"
+        )));
+    }
+
+    if plant.retired {
+        return Err(GeonftError::AnyhowError(anyhow!(
+            "Treasure already retired"
+        )));
+    }
+
+    plant.retired = true;
+
+    Ok(())
+}
+
+/// The mint of a treasure NFT, a PDA that is also its own mint authority.
+///
+/// Treasure public keys are longer than a seed can be,
+/// so the mint is derived from their hash.
+pub fn find_treasure_mint_address(
+    program_id: &Pubkey,
+    program_instance: &Pubkey,
+    treasure_public_key: &[u8],
+) -> (Pubkey, u8) {
+    let treasure_hash = hash(treasure_public_key);
+    Pubkey::find_program_address(
+        &[MINT_SEED, program_instance.as_ref(), treasure_hash.as_ref()],
+        program_id,
+    )
+}
+
+/// Build a `MintTreasure` instruction.
+///
+/// The accounts are
+///
+/// - the program instance, writable
+/// - the payer, signer and writable
+/// - the treasure mint from `find_treasure_mint_address`, writable
+/// - the wallet designated by the claim
+/// - the wallet's associated token account, writable
+/// - the system, token and associated token programs
+/// - the rent sysvar
+/// - optionally, the Metaplex token metadata program,
+///   and the mint's metadata account, writable
+#[allow(clippy::too_many_arguments)]
+pub fn mint_treasure_instruction(
+    program_id: &Pubkey,
+    program_instance: &Pubkey,
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    treasure_public_key: Vec<u8>,
+    name: String,
+    with_metadata: bool,
+) -> Result<Instruction, std::io::Error> {
+    let (mint, _) = find_treasure_mint_address(program_id, program_instance, &treasure_public_key);
+
+    let mut accounts = vec![
+        AccountMeta::new(*program_instance, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(mint, false),
+        AccountMeta::new_readonly(*wallet, false),
+        AccountMeta::new(
+            spl_associated_token_account::get_associated_token_address(wallet, &mint),
+            false,
+        ),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    if with_metadata {
+        let (metadata_account, _) = metadata::find_metadata_address(&mint);
+        accounts.push(AccountMeta::new_readonly(metadata::id(), false));
+        accounts.push(AccountMeta::new(metadata_account, false));
+    }
+
+    let data = GeonftRequestSolana::MintTreasure(MintRequestSolana {
+        treasure_public_key,
+        name,
+    })
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Mint a claimed treasure as a supply-1 token
+/// to the wallet designated by its claim.
+///
+/// The mint authority is removed afterwards,
+/// so no more of the token can ever be minted.
+pub fn mint_treasure(
+    program_id: &Pubkey,
+    program_instance: &Pubkey,
+    accounts: &[AccountInfo],
+    mint_info: MintRequestSolana,
+    treasure_data: &mut Treasure,
+) -> Result<(), GeonftError> {
+    msg!("mint_treasure");
+
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let mint = next_account_info(accounts_iter)?;
+    let wallet = next_account_info(accounts_iter)?;
+    let wallet_token_account = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let associated_token_program = next_account_info(accounts_iter)?;
+    let rent_sysvar = next_account_info(accounts_iter)?;
+    // Only passed where the Metaplex program is deployed
+    let metadata_accounts = match (
+        next_account_info(accounts_iter),
+        next_account_info(accounts_iter),
+    ) {
+        (Ok(metadata_program), Ok(metadata_account)) => Some((metadata_program, metadata_account)),
+        _ => None,
+    };
+
+    if !payer.is_signer {
+        return Err(GeonftError::SolanaError(
+            ProgramError::MissingRequiredSignature,
+        ));
+    }
+
+    if *system_program_account.key != system_program::id()
+        || *token_program.key != spl_token::id()
+        || *associated_token_program.key != spl_associated_token_account::id()
+    {
+        return Err(GeonftError::SolanaError(ProgramError::IncorrectProgramId));
+    }
+
+    let treasure_pubkey = &mint_info.treasure_public_key;
+
+    let metadata_uri = match treasure_data.plant_treasure.get(treasure_pubkey) {
+        Some(plant) => plant.metadata_uri.clone(),
+        None => return Err(GeonftError::AnyhowError(anyhow!("Treasure doesn't exist"))),
+    };
+
+    let claim = match treasure_data.claim_treasure.get_mut(treasure_pubkey) {
+        Some(claim) => claim,
+        None => return Err(GeonftError::AnyhowError(anyhow!("Treasure isn't claimed"))),
+    };
+
+    if claim.mint.is_some() {
+        return Err(GeonftError::AnyhowError(anyhow!("Treasure already minted")));
+    }
+
+    match &claim.wallet {
+        Some(claim_wallet) if claim_wallet.as_slice() == wallet.key.as_ref() => {}
+        Some(_) => {
+            return Err(GeonftError::AnyhowError(anyhow!(
+                "Wallet isn't the one designated by the claim"
+            )))
+        }
+        None => {
+            return Err(GeonftError::AnyhowError(anyhow!(
+                "Claim doesn't designate a wallet"
+            )))
+        }
+    }
+
+    let (mint_address, bump) =
+        find_treasure_mint_address(program_id, program_instance, treasure_pubkey);
+    if *mint.key != mint_address {
+        return Err(GeonftError::SolanaError(ProgramError::InvalidSeeds));
+    }
+
+    let treasure_hash = hash(treasure_pubkey);
+    let mint_seeds: &[&[u8]] = &[
+        MINT_SEED,
+        program_instance.as_ref(),
+        treasure_hash.as_ref(),
+        &[bump],
+    ];
+
+    let rent = Rent::from_account_info(rent_sysvar)?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            mint.key,
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        &[payer.clone(), mint.clone(), system_program_account.clone()],
+        &[mint_seeds],
+    )?;
+
+    invoke(
+        &spl_token::instruction::initialize_mint(&spl_token::id(), mint.key, mint.key, None, 0)?,
+        &[mint.clone(), rent_sysvar.clone(), token_program.clone()],
+    )?;
+
+    invoke(
+        &spl_associated_token_account::create_associated_token_account(
+            payer.key, wallet.key, mint.key,
+        ),
+        &[
+            payer.clone(),
+            wallet_token_account.clone(),
+            wallet.clone(),
+            mint.clone(),
+            system_program_account.clone(),
+            token_program.clone(),
+            rent_sysvar.clone(),
+            associated_token_program.clone(),
+        ],
+    )?;
+
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            &spl_token::id(),
+            mint.key,
+            wallet_token_account.key,
+            mint.key,
+            &[],
+            1,
+        )?,
+        &[
+            mint.clone(),
+            wallet_token_account.clone(),
+            token_program.clone(),
+        ],
+        &[mint_seeds],
+    )?;
+
+    if let Some((metadata_program, metadata_account)) = metadata_accounts {
+        if *metadata_program.key != metadata::id() {
+            return Err(GeonftError::SolanaError(ProgramError::IncorrectProgramId));
+        }
+
+        if mint_info.name.len() > metadata::MAX_NAME_LENGTH {
+            return Err(GeonftError::AnyhowError(anyhow!("NFT name too long")));
+        }
+
+        invoke_signed(
+            &metadata::create_metadata_account(
+                metadata_account.key,
+                mint.key,
+                mint.key,
+                payer.key,
+                payer.key,
+                mint_info.name,
+                TREASURE_SYMBOL.to_string(),
+                metadata_uri,
+            )?,
+            &[
+                metadata_account.clone(),
+                mint.clone(),
+                payer.clone(),
+                system_program_account.clone(),
+                rent_sysvar.clone(),
+                metadata_program.clone(),
+            ],
+            &[mint_seeds],
+        )?;
+    }
+
+    invoke_signed(
+        &spl_token::instruction::set_authority(
+            &spl_token::id(),
+            mint.key,
+            None,
+            AuthorityType::MintTokens,
+            mint.key,
+            &[],
+        )?,
+        &[mint.clone(), token_program.clone()],
+        &[mint_seeds],
+    )?;
+
+    claim.mint = Some(mint.key.to_bytes().to_vec());
+
+    Ok(())
+}
+
 pub enum GeonftError {
     SolanaError(ProgramError),
     AnyhowError(anyhow::Error),
@@ -132,6 +561,12 @@ impl From<std::io::Error> for GeonftError {
     }
 }
 
+impl From<ProgramError> for GeonftError {
+    fn from(e: ProgramError) -> Self {
+        GeonftError::SolanaError(e)
+    }
+}
+
 impl From<GeonftError> for ProgramError {
     fn from(e: GeonftError) -> Self {
         match e {
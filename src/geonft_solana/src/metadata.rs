@@ -0,0 +1,150 @@
+//! Just enough of the Metaplex token metadata program's interface
+//! to give a treasure mint a metadata account,
+//! so wallets and explorers show it as an NFT.
+//!
+//! The instruction is encoded by hand,
+//! matching the program's `CreateMetadataAccount` instruction.
+
+use borsh::BorshSerialize;
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::pubkey::Pubkey;
+use solana_program::{system_program, sysvar};
+
+solana_program::declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+pub const MAX_NAME_LENGTH: usize = 32;
+pub const MAX_SYMBOL_LENGTH: usize = 10;
+pub const MAX_URI_LENGTH: usize = 200;
+
+static METADATA_SEED: &[u8] = b"metadata";
+
+#[derive(BorshSerialize)]
+struct Creator {
+    address: [u8; 32],
+    verified: bool,
+    share: u8,
+}
+
+#[derive(BorshSerialize)]
+struct Data {
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+    creators: Option<Vec<Creator>>,
+}
+
+#[derive(BorshSerialize)]
+struct CreateMetadataAccountArgs {
+    data: Data,
+    is_mutable: bool,
+}
+
+#[derive(BorshSerialize)]
+enum MetadataInstruction {
+    CreateMetadataAccount(CreateMetadataAccountArgs),
+}
+
+/// The metadata account of a mint
+pub fn find_metadata_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[METADATA_SEED, id().as_ref(), mint.as_ref()], &id())
+}
+
+/// Create an immutable metadata account with no creators or royalties.
+#[allow(clippy::too_many_arguments)]
+pub fn create_metadata_account(
+    metadata: &Pubkey,
+    mint: &Pubkey,
+    mint_authority: &Pubkey,
+    payer: &Pubkey,
+    update_authority: &Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+) -> Result<Instruction, std::io::Error> {
+    let data = MetadataInstruction::CreateMetadataAccount(CreateMetadataAccountArgs {
+        data: Data {
+            name,
+            symbol,
+            uri,
+            seller_fee_basis_points: 0,
+            creators: None,
+        },
+        is_mutable: false,
+    })
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*metadata, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*mint_authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*update_authority, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_metadata_account_layout() {
+        let metadata = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mint_authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let update_authority = Pubkey::new_unique();
+
+        let instruction = create_metadata_account(
+            &metadata,
+            &mint,
+            &mint_authority,
+            &payer,
+            &update_authority,
+            "Treasure".to_string(),
+            "TREASURE".to_string(),
+            "https://treasuretree.org".to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(instruction.program_id, id());
+        assert_eq!(
+            instruction.accounts,
+            vec![
+                AccountMeta::new(metadata, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(mint_authority, true),
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(update_authority, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ]
+        );
+
+        let expected_data = [
+            // CreateMetadataAccount
+            &[0][..],
+            // Strings are prefixed by their little-endian u32 length
+            &[8, 0, 0, 0],
+            b"Treasure",
+            &[8, 0, 0, 0],
+            b"TREASURE",
+            &[24, 0, 0, 0],
+            b"https://treasuretree.org",
+            // No seller fee
+            &[0, 0],
+            // No creators
+            &[0],
+            // Immutable
+            &[0],
+        ]
+        .concat();
+        assert_eq!(instruction.data, expected_data);
+    }
+}
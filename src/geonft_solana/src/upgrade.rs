@@ -0,0 +1,82 @@
+//! Upgrading program instances written with older data layouts
+//!
+//! Old layouts are only ever read here,
+//! to be reserialized in the current layout by an `UpgradeInstance` request.
+
+use crate::{ClaimTreasure, GeonftError, PlantTreasure, Treasure, CURRENT_LAYOUT, LAYOUT_V1};
+use anyhow::anyhow;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::account_info::AccountInfo;
+use solana_program::borsh::try_from_slice_unchecked;
+use solana_program::msg;
+use std::collections::BTreeMap;
+
+#[derive(BorshDeserialize, Debug)]
+struct TreasureV1 {
+    plant_treasure: BTreeMap<Vec<u8>, PlantTreasureV1>,
+    claim_treasure: BTreeMap<Vec<u8>, ClaimTreasureV1>,
+}
+
+#[derive(BorshDeserialize, Debug)]
+struct PlantTreasureV1 {
+    account_pubkey: Vec<u8>,
+    treasure_hash: Vec<u8>,
+}
+
+#[derive(BorshDeserialize, Debug)]
+struct ClaimTreasureV1 {
+    account_pubkey: Vec<u8>,
+}
+
+/// Reserialize the instance's data from `layout` to the current layout.
+pub fn upgrade_instance(account: &AccountInfo, layout: u8) -> Result<(), GeonftError> {
+    msg!("upgrade_instance from layout {}", layout);
+
+    let treasure_data = match layout {
+        LAYOUT_V1 => from_v1(try_from_slice_unchecked(&account.data.borrow()[1..])?),
+        _ => {
+            return Err(GeonftError::AnyhowError(anyhow!(
+                "Unknown program instance layout {}",
+                layout
+            )))
+        }
+    };
+
+    treasure_data.serialize(&mut &mut account.data.borrow_mut()[1..])?;
+    account.data.borrow_mut()[0] = CURRENT_LAYOUT;
+
+    Ok(())
+}
+
+/// V1 plants have no metadata URI, so they are minted with an empty one.
+fn from_v1(v1: TreasureV1) -> Treasure {
+    Treasure {
+        plant_treasure: v1
+            .plant_treasure
+            .into_iter()
+            .map(|(key, plant)| {
+                let plant = PlantTreasure {
+                    account_pubkey: plant.account_pubkey,
+                    treasure_hash: plant.treasure_hash,
+                    metadata_uri: String::new(),
+                    retired: false,
+                    not_before: None,
+                    not_after: None,
+                };
+                (key, plant)
+            })
+            .collect(),
+        claim_treasure: v1
+            .claim_treasure
+            .into_iter()
+            .map(|(key, claim)| {
+                let claim = ClaimTreasure {
+                    account_pubkey: claim.account_pubkey,
+                    wallet: None,
+                    mint: None,
+                };
+                (key, claim)
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,26 @@
+//! Claiming treasures
+
+use common::start;
+use solana_program::pubkey::Pubkey;
+
+mod common;
+
+#[tokio::test]
+async fn claim_needs_plant() {
+    let mut test = start().await;
+
+    assert!(test.send(test.claim(None)).await.is_err());
+}
+
+#[tokio::test]
+async fn claim_only_once() {
+    let mut test = start().await;
+    let wallet = Pubkey::new_unique();
+    test.plant_and_claim(Some(&wallet)).await;
+
+    // A resent claim, with another wallet so it isn't deduplicated,
+    // mustn't replace the designated wallet
+    let other_wallet = Pubkey::new_unique();
+    assert!(test.send(test.claim(Some(&other_wallet))).await.is_err());
+    assert!(test.send(test.claim(None)).await.is_err());
+}
@@ -0,0 +1,111 @@
+//! Claiming treasures with claim windows
+//!
+//! Windows are checked at the claim's time,
+//! which may be up to `MAX_CLAIM_DELAY` before the claim is processed.
+
+use common::start;
+use geonft_solana::MAX_CLAIM_DELAY;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod common;
+
+const HOUR: i64 = 60 * 60;
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[tokio::test]
+async fn claim_before_window() {
+    let mut test = start().await;
+    let now = unix_time();
+    test.send(test.plant(Some(now - HOUR), Some(now + HOUR)))
+        .await
+        .unwrap();
+
+    assert!(test
+        .send(test.claim_at(None, Some(now - 2 * HOUR)))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn claim_inside_window() {
+    let mut test = start().await;
+    let now = unix_time();
+    test.send(test.plant(Some(now - 3 * HOUR), Some(now - HOUR)))
+        .await
+        .unwrap();
+
+    // Synced after the window closed
+    test.send(test.claim_at(None, Some(now - 2 * HOUR)))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn claim_after_window() {
+    let mut test = start().await;
+    let now = unix_time();
+    test.send(test.plant(Some(now - 3 * HOUR), Some(now - HOUR)))
+        .await
+        .unwrap();
+
+    assert!(test
+        .send(test.claim_at(None, Some(now - HOUR / 2)))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn claim_too_old() {
+    let mut test = start().await;
+    let now = unix_time();
+    let not_after = now - MAX_CLAIM_DELAY - HOUR;
+    test.send(test.plant(Some(not_after - HOUR), Some(not_after)))
+        .await
+        .unwrap();
+
+    // Inside the window, but too long ago
+    assert!(test
+        .send(test.claim_at(None, Some(not_after - HOUR / 2)))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn claim_without_time_is_checked_now() {
+    let mut test = start().await;
+    let now = unix_time();
+    test.send(test.plant(Some(now - 3 * HOUR), Some(now - HOUR)))
+        .await
+        .unwrap();
+
+    assert!(test.send(test.claim(None)).await.is_err());
+}
+
+#[tokio::test]
+async fn claim_in_the_future() {
+    let mut test = start().await;
+    test.send(test.plant(None, None)).await.unwrap();
+
+    let next_day = unix_time() + 24 * HOUR;
+    assert!(test
+        .send(test.claim_at(None, Some(next_day)))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn plant_empty_window() {
+    let mut test = start().await;
+    let now = unix_time();
+
+    assert!(test
+        .send(test.plant(Some(now + HOUR), Some(now - HOUR)))
+        .await
+        .is_err());
+}
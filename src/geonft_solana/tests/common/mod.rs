@@ -0,0 +1,161 @@
+//! A program instance running in `solana-program-test`,
+//! shared by the integration tests.
+
+#![allow(dead_code)]
+
+use borsh::BorshSerialize;
+use geonft_request::{
+    ClaimRequestSolana, GeonftRequestSolana, PlantRequestSolana, RetireRequestSolana,
+};
+use geonft_solana::{metadata, mint_treasure_instruction, process_instruction};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::account::Account;
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use solana_sdk::transport::TransportError;
+
+pub const INSTANCE_SIZE: usize = 10_000;
+
+pub struct Test {
+    pub banks_client: BanksClient,
+    pub payer: Keypair,
+    pub recent_blockhash: Hash,
+    pub program_id: Pubkey,
+    pub instance: Pubkey,
+    pub treasure_public_key: Vec<u8>,
+    pub planter_public_key: Vec<u8>,
+    pub claimer_public_key: Vec<u8>,
+}
+
+/// Start a program with an empty instance account.
+pub async fn start() -> Test {
+    start_with_instance_data(vec![0; INSTANCE_SIZE]).await
+}
+
+/// Start a program with an instance account holding `data`.
+pub async fn start_with_instance_data(data: Vec<u8>) -> Test {
+    start_with(data, false).await
+}
+
+/// Start a program with an empty instance account,
+/// and the Metaplex token metadata program,
+/// built to `tests/fixtures/mpl_token_metadata.so`.
+pub async fn start_with_metadata_program() -> Test {
+    start_with(vec![0; INSTANCE_SIZE], true).await
+}
+
+async fn start_with(data: Vec<u8>, with_metadata_program: bool) -> Test {
+    let program_id = Pubkey::new_unique();
+    let instance = Pubkey::new_unique();
+
+    let mut program_test =
+        ProgramTest::new("geonft_solana", program_id, processor!(process_instruction));
+    if with_metadata_program {
+        program_test.add_program("mpl_token_metadata", metadata::id(), None);
+    }
+    program_test.add_account(
+        instance,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+
+    let (banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    Test {
+        banks_client,
+        payer,
+        recent_blockhash,
+        program_id,
+        instance,
+        // Compressed k256 public keys
+        treasure_public_key: vec![2; 33],
+        planter_public_key: vec![3; 33],
+        claimer_public_key: vec![5; 33],
+    }
+}
+
+impl Test {
+    pub async fn send(&mut self, instruction: Instruction) -> Result<(), TransportError> {
+        let mut tx = Transaction::new_with_payer(&[instruction], Some(&self.payer.pubkey()));
+        tx.sign(&[&self.payer], self.recent_blockhash);
+        self.banks_client.process_transaction(tx).await
+    }
+
+    pub fn request(&self, request: GeonftRequestSolana) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![AccountMeta::new(self.instance, false)],
+            data: request.try_to_vec().unwrap(),
+        }
+    }
+
+    pub fn plant(&self, not_before: Option<i64>, not_after: Option<i64>) -> Instruction {
+        self.request(GeonftRequestSolana::PlantTreasure(PlantRequestSolana {
+            account_public_key: self.planter_public_key.clone(),
+            treasure_public_key: self.treasure_public_key.clone(),
+            treasure_hash: vec![4; 64],
+            metadata_uri: "https://treasuretree.org/api/treasures/gtp1/metadata.json".to_string(),
+            not_before,
+            not_after,
+        }))
+    }
+
+    pub fn claim(&self, wallet: Option<&Pubkey>) -> Instruction {
+        self.claim_at(wallet, None)
+    }
+
+    pub fn claim_at(&self, wallet: Option<&Pubkey>, claimed_at: Option<i64>) -> Instruction {
+        self.request(GeonftRequestSolana::ClaimTreasure(ClaimRequestSolana {
+            account_public_key: self.claimer_public_key.clone(),
+            treasure_public_key: self.treasure_public_key.clone(),
+            wallet: wallet.map(|wallet| wallet.to_bytes().to_vec()),
+            claimed_at,
+        }))
+    }
+
+    pub fn retire(&self, account_public_key: Vec<u8>) -> Instruction {
+        self.request(GeonftRequestSolana::RetireTreasure(RetireRequestSolana {
+            account_public_key,
+            treasure_public_key: self.treasure_public_key.clone(),
+        }))
+    }
+
+    pub async fn plant_and_claim(&mut self, wallet: Option<&Pubkey>) {
+        self.send(self.plant(None, None)).await.unwrap();
+        self.send(self.claim(wallet)).await.unwrap();
+    }
+
+    pub fn mint(&self, wallet: &Pubkey, name: &str) -> Instruction {
+        self.mint_instruction(wallet, name, false)
+    }
+
+    /// Mint with a Metaplex metadata account,
+    /// needing `start_with_metadata_program`
+    pub fn mint_with_metadata(&self, wallet: &Pubkey, name: &str) -> Instruction {
+        self.mint_instruction(wallet, name, true)
+    }
+
+    fn mint_instruction(&self, wallet: &Pubkey, name: &str, with_metadata: bool) -> Instruction {
+        mint_treasure_instruction(
+            &self.program_id,
+            &self.instance,
+            &self.payer.pubkey(),
+            wallet,
+            self.treasure_public_key.clone(),
+            name.to_string(),
+            with_metadata,
+        )
+        .unwrap()
+    }
+
+    pub async fn account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.banks_client.get_account(*address).await.unwrap()
+    }
+}
@@ -0,0 +1,135 @@
+//! Minting claimed treasures, against the SPL programs built into
+//! `solana-program-test`.
+//!
+//! Minting creates accounts through cross-program invocations,
+//! which `solana-program-test` only supports between BPF programs,
+//! so tests that mint successfully only run with `cargo test-bpf`.
+//! Minting with metadata also needs the Metaplex token metadata program
+//! built to `tests/fixtures/mpl_token_metadata.so`.
+
+use borsh::BorshDeserialize;
+use common::{start, start_with_metadata_program};
+use geonft_solana::{find_treasure_mint_address, metadata, TREASURE_SYMBOL};
+use solana_program::borsh::try_from_slice_unchecked;
+use solana_program::program_option::COption;
+use solana_program::program_pack::Pack;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+
+mod common;
+
+/// The start of a Metaplex metadata account
+#[derive(BorshDeserialize)]
+struct MetadataAccount {
+    _key: u8,
+    update_authority: Pubkey,
+    mint: Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn mint_to_claim_wallet() {
+    let mut test = start().await;
+    let wallet = Pubkey::new_unique();
+    test.plant_and_claim(Some(&wallet)).await;
+
+    test.send(test.mint(&wallet, "Treasure")).await.unwrap();
+
+    let (mint_address, _) =
+        find_treasure_mint_address(&test.program_id, &test.instance, &test.treasure_public_key);
+    let token_address =
+        spl_associated_token_account::get_associated_token_address(&wallet, &mint_address);
+
+    let mint = test.account(&mint_address).await.unwrap();
+    let mint = spl_token::state::Mint::unpack(&mint.data).unwrap();
+    assert_eq!(mint.supply, 1);
+    assert_eq!(mint.decimals, 0);
+    assert_eq!(mint.mint_authority, COption::None);
+
+    let token = test.account(&token_address).await.unwrap();
+    let token = spl_token::state::Account::unpack(&token.data).unwrap();
+    assert_eq!(token.mint, mint_address);
+    assert_eq!(token.owner, wallet);
+    assert_eq!(token.amount, 1);
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn mint_only_once() {
+    let mut test = start().await;
+    let wallet = Pubkey::new_unique();
+    test.plant_and_claim(Some(&wallet)).await;
+
+    test.send(test.mint(&wallet, "Treasure")).await.unwrap();
+    // A different name so this isn't deduplicated as the same transaction
+    assert!(test
+        .send(test.mint(&wallet, "Treasure again"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn mint_with_metadata() {
+    let mut test = start_with_metadata_program().await;
+    let wallet = Pubkey::new_unique();
+    test.plant_and_claim(Some(&wallet)).await;
+
+    test.send(test.mint_with_metadata(&wallet, "Treasure"))
+        .await
+        .unwrap();
+
+    let (mint_address, _) =
+        find_treasure_mint_address(&test.program_id, &test.instance, &test.treasure_public_key);
+    let (metadata_address, _) = metadata::find_metadata_address(&mint_address);
+
+    let account = test.account(&metadata_address).await.unwrap();
+    assert_eq!(account.owner, metadata::id());
+    let metadata: MetadataAccount = try_from_slice_unchecked(&account.data).unwrap();
+    assert_eq!(metadata.mint, mint_address);
+    assert_eq!(metadata.update_authority, test.payer.pubkey());
+    // Metaplex pads these to their longest length
+    assert_eq!(metadata.name.trim_end_matches('\0'), "Treasure");
+    assert_eq!(metadata.symbol.trim_end_matches('\0'), TREASURE_SYMBOL);
+    assert_eq!(
+        metadata.uri.trim_end_matches('\0'),
+        "https://treasuretree.org/api/treasures/gtp1/metadata.json"
+    );
+}
+
+#[tokio::test]
+async fn mint_only_to_claim_wallet() {
+    let mut test = start().await;
+    let wallet = Pubkey::new_unique();
+    let other_wallet = Pubkey::new_unique();
+    test.plant_and_claim(Some(&wallet)).await;
+
+    assert!(test
+        .send(test.mint(&other_wallet, "Treasure"))
+        .await
+        .is_err());
+
+    let (mint_address, _) =
+        find_treasure_mint_address(&test.program_id, &test.instance, &test.treasure_public_key);
+    assert!(test.account(&mint_address).await.is_none());
+}
+
+#[tokio::test]
+async fn mint_needs_claim_wallet() {
+    let mut test = start().await;
+    let wallet = Pubkey::new_unique();
+    test.plant_and_claim(None).await;
+
+    assert!(test.send(test.mint(&wallet, "Treasure")).await.is_err());
+}
+
+#[tokio::test]
+async fn mint_needs_claim() {
+    let mut test = start().await;
+    let wallet = Pubkey::new_unique();
+
+    assert!(test.send(test.mint(&wallet, "Treasure")).await.is_err());
+}
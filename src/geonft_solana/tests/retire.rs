@@ -0,0 +1,50 @@
+//! Retiring treasures
+
+use common::start;
+
+mod common;
+
+#[tokio::test]
+async fn retire_by_planter() {
+    let mut test = start().await;
+    test.send(test.plant(None, None)).await.unwrap();
+
+    test.send(test.retire(test.planter_public_key.clone()))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn retire_only_by_planter() {
+    let mut test = start().await;
+    test.send(test.plant(None, None)).await.unwrap();
+
+    assert!(test
+        .send(test.retire(test.claimer_public_key.clone()))
+        .await
+        .is_err());
+
+    // Still unretired, so it can be claimed
+    test.send(test.claim(None)).await.unwrap();
+}
+
+#[tokio::test]
+async fn retire_needs_plant() {
+    let mut test = start().await;
+
+    assert!(test
+        .send(test.retire(test.planter_public_key.clone()))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn claim_after_retire() {
+    let mut test = start().await;
+    test.send(test.plant(None, None)).await.unwrap();
+    test.send(test.retire(test.planter_public_key.clone()))
+        .await
+        .unwrap();
+
+    assert!(test.send(test.claim(None)).await.is_err());
+}
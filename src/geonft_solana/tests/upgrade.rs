@@ -0,0 +1,115 @@
+//! Upgrading program instances written by older versions of the program
+
+use borsh::BorshSerialize;
+use common::{start_with_instance_data, INSTANCE_SIZE};
+use geonft_request::GeonftRequestSolana;
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+
+mod common;
+
+/// The original instance layout, version 1
+#[derive(BorshSerialize)]
+struct TreasureV1 {
+    plant_treasure: BTreeMap<Vec<u8>, PlantTreasureV1>,
+    claim_treasure: BTreeMap<Vec<u8>, ClaimTreasureV1>,
+}
+
+#[derive(BorshSerialize)]
+struct PlantTreasureV1 {
+    account_pubkey: Vec<u8>,
+    treasure_hash: Vec<u8>,
+}
+
+#[derive(BorshSerialize)]
+struct ClaimTreasureV1 {
+    account_pubkey: Vec<u8>,
+}
+
+/// A version 1 instance with one planted and one claimed treasure
+fn instance_data_v1(planted: &[u8], claimed: &[u8], planter: &[u8], claimer: &[u8]) -> Vec<u8> {
+    let mut plant_treasure = BTreeMap::new();
+    for treasure in &[planted, claimed] {
+        plant_treasure.insert(
+            treasure.to_vec(),
+            PlantTreasureV1 {
+                account_pubkey: planter.to_vec(),
+                treasure_hash: vec![4; 64],
+            },
+        );
+    }
+
+    let mut claim_treasure = BTreeMap::new();
+    claim_treasure.insert(
+        claimed.to_vec(),
+        ClaimTreasureV1 {
+            account_pubkey: claimer.to_vec(),
+        },
+    );
+
+    let mut data = vec![1];
+    TreasureV1 {
+        plant_treasure,
+        claim_treasure,
+    }
+    .serialize(&mut data)
+    .unwrap();
+    data.resize(INSTANCE_SIZE, 0);
+    data
+}
+
+#[tokio::test]
+async fn upgrade_from_v1() {
+    let planted = vec![2; 33];
+    let claimed = vec![6; 33];
+    let mut test =
+        start_with_instance_data(instance_data_v1(&planted, &claimed, &[3; 33], &[5; 33])).await;
+    assert_eq!(test.treasure_public_key, planted);
+
+    let wallet = Pubkey::new_unique();
+
+    // Nothing but an upgrade is accepted until the instance is upgraded.
+    // No wallet, so this isn't the same transaction as the claim below.
+    assert!(test.send(test.claim(None)).await.is_err());
+
+    test.send(test.request(GeonftRequestSolana::UpgradeInstance))
+        .await
+        .unwrap();
+
+    let instance = test.instance;
+    let instance = test.account(&instance).await.unwrap();
+    assert_eq!(instance.data[0], geonft_solana::CURRENT_LAYOUT);
+
+    // The V1 plant is kept, so it can be claimed
+    test.send(test.claim(Some(&wallet))).await.unwrap();
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn mint_after_upgrade_from_v1() {
+    let planted = vec![2; 33];
+    let claimed = vec![6; 33];
+    let mut test =
+        start_with_instance_data(instance_data_v1(&planted, &claimed, &[3; 33], &[5; 33])).await;
+    let wallet = Pubkey::new_unique();
+
+    test.send(test.request(GeonftRequestSolana::UpgradeInstance))
+        .await
+        .unwrap();
+
+    // V1 plants have no metadata URI, but can still be minted
+    test.send(test.claim(Some(&wallet))).await.unwrap();
+    test.send(test.mint(&wallet, "Treasure")).await.unwrap();
+}
+
+#[tokio::test]
+async fn upgrade_current_instance() {
+    let mut test = common::start().await;
+    test.send(test.plant(None, None)).await.unwrap();
+
+    test.send(test.request(GeonftRequestSolana::UpgradeInstance))
+        .await
+        .unwrap();
+
+    test.send(test.claim(None)).await.unwrap();
+}
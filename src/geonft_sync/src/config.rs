@@ -0,0 +1,184 @@
+//! Command line and config file options
+//!
+//! Every option can be given on the command line or in a TOML config file,
+//! with the command line taking precedence.
+//! The RPC URL and payer keypair fall back to the Solana CLI config.
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use crate::metrics::HealthConfig;
+
+static DEFAULT_INSTANCE_SEED: &str = "geonft";
+static DEFAULT_BASE_URL: &str = "https://treasuretree.org";
+const DEFAULT_INSTANCE_SIZE: usize = 10_000;
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "geonft_sync",
+    about = "Synchronizes TreasureTree plants and claims to Solana"
+)]
+pub struct Opts {
+    /// Path to a TOML config file
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// The geonft_web working directory, which contains `data/`
+    #[structopt(long, parse(from_os_str))]
+    pub data_dir: Option<PathBuf>,
+    /// URL of the Solana JSON RPC node
+    #[structopt(long)]
+    pub rpc_url: Option<String>,
+    /// Keypair file of the account that pays for transactions
+    #[structopt(long, parse(from_os_str))]
+    pub payer_keypair: Option<PathBuf>,
+    /// Address of the deployed geonft_solana program
+    #[structopt(long)]
+    pub program_id: Option<Pubkey>,
+    /// Origin of the geonft_web site, used in the NFT metadata URLs recorded on chain
+    #[structopt(long)]
+    pub base_url: Option<String>,
+    /// Seconds between full scans of the data directory
+    #[structopt(long)]
+    pub interval: Option<u64>,
+    /// Address to serve `/metrics` and `/healthz` on, e.g. `127.0.0.1:9100`
+    #[structopt(long)]
+    pub metrics_addr: Option<SocketAddr>,
+    /// Run a single round of syncing and exit
+    #[structopt(long)]
+    pub once: bool,
+    /// Print the planned steps and their estimated fees, then exit
+    /// without sending any transactions
+    #[structopt(long)]
+    pub dry_run: bool,
+}
+
+/// The contents of the TOML config file
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    data_dir: Option<PathBuf>,
+    rpc_url: Option<String>,
+    payer_keypair: Option<PathBuf>,
+    program_id: Option<String>,
+    base_url: Option<String>,
+    interval: Option<u64>,
+    /// Seed used to derive the program instance account from the payer
+    instance_seed: Option<String>,
+    /// Size in bytes of the program instance account
+    instance_size: Option<usize>,
+    /// Create Metaplex metadata for minted treasures,
+    /// which needs the Metaplex program deployed on the cluster.
+    /// Off by default so a local validator with only our program works.
+    mint_metadata: Option<bool>,
+    metrics_addr: Option<SocketAddr>,
+    /// `/healthz` fails if no round completed within this many seconds
+    health_max_round_age: Option<u64>,
+    /// `/healthz` fails if the payer has fewer lamports than this
+    health_min_payer_balance: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub data_dir: Option<PathBuf>,
+    pub rpc_url: String,
+    pub payer_keypair: PathBuf,
+    pub program_id: Pubkey,
+    pub base_url: String,
+    pub interval: Duration,
+    pub instance_seed: String,
+    pub instance_size: usize,
+    pub mint_metadata: bool,
+    pub metrics_addr: Option<SocketAddr>,
+    pub health: HealthConfig,
+    pub once: bool,
+    pub dry_run: bool,
+}
+
+pub fn load() -> Result<Config> {
+    let opts = Opts::from_args();
+
+    let file = match &opts.config {
+        Some(path) => {
+            info!("loading config from {}", path.display());
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("unable to read config file {}", path.display()))?;
+            toml::from_str(&text)
+                .with_context(|| format!("unable to parse config file {}", path.display()))?
+        }
+        None => ConfigFile::default(),
+    };
+
+    let solana_cli_config::Config {
+        json_rpc_url,
+        keypair_path,
+        ..
+    } = load_solana_cli_config();
+
+    // Only the program's address is needed,
+    // so the program's deploy keypair never has to be on the sync host.
+    let program_id = match (opts.program_id, file.program_id) {
+        (Some(id), _) => id,
+        (None, Some(id)) => id
+            .parse()
+            .map_err(|e| anyhow!("invalid program id {}: {}", id, e))?,
+        (None, None) => bail!("no program id configured, set --program-id or `program_id`"),
+    };
+
+    let base_url = opts
+        .base_url
+        .or(file.base_url)
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+    if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+        bail!("base url must be http or https: {}", base_url);
+    }
+    let base_url = base_url.trim_end_matches('/').to_string();
+
+    let interval = opts
+        .interval
+        .or(file.interval)
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    // A zero interval would make every round a full scan, with no wait
+    if interval == 0 {
+        bail!("interval must be at least one second");
+    }
+
+    Ok(Config {
+        data_dir: opts.data_dir.or(file.data_dir),
+        rpc_url: opts.rpc_url.or(file.rpc_url).unwrap_or(json_rpc_url),
+        payer_keypair: opts
+            .payer_keypair
+            .or(file.payer_keypair)
+            .unwrap_or_else(|| PathBuf::from(keypair_path)),
+        program_id,
+        base_url,
+        interval: Duration::from_secs(interval),
+        instance_seed: file
+            .instance_seed
+            .unwrap_or_else(|| DEFAULT_INSTANCE_SEED.to_string()),
+        instance_size: file.instance_size.unwrap_or(DEFAULT_INSTANCE_SIZE),
+        mint_metadata: file.mint_metadata.unwrap_or(false),
+        metrics_addr: opts.metrics_addr.or(file.metrics_addr),
+        health: HealthConfig {
+            // Allow a few full scans to be slow before complaining
+            max_round_age: Duration::from_secs(file.health_max_round_age.unwrap_or(interval * 3)),
+            min_payer_balance: file.health_min_payer_balance.unwrap_or(0),
+        },
+        once: opts.once,
+        dry_run: opts.dry_run,
+    })
+}
+
+fn load_solana_cli_config() -> solana_cli_config::Config {
+    solana_cli_config::CONFIG_FILE
+        .as_ref()
+        .and_then(|path| solana_cli_config::Config::load(path).ok())
+        .unwrap_or_default()
+}
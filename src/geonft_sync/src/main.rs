@@ -1,19 +1,87 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use log::{error, info, warn};
-use std::collections::HashMap;
-use std::thread;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::time::{Duration, Instant};
 
 use geonft_shared::io::{self, SyncStatus};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::Signer;
+use watch::{Changes, DataWatcher};
 
+mod config;
+mod metrics;
 mod solana;
+mod watch;
 
 fn main() -> Result<()> {
     env_logger::init();
 
-    loop {
+    let config = config::load()?;
+    let solana_config = solana::load_config(&config)?;
+
+    if let Some(data_dir) = &config.data_dir {
+        info!("changing to data directory {}", data_dir.display());
+        env::set_current_dir(data_dir)
+            .with_context(|| format!("unable to change to {}", data_dir.display()))?;
+    }
+
+    if let Some(addr) = config.metrics_addr {
+        metrics::serve(addr, config.health.clone())?;
+    }
+
+    if config.dry_run {
+        let plan = make_plan()?;
+        return print_plan(&plan, &solana_config);
+    }
+
+    if config.once {
         let plan = make_plan()?;
-        execute_plan(plan)?;
-        wait_for_next_round();
+        return execute_plan(plan, &solana_config);
+    }
+
+    let mut watcher = DataWatcher::new()?;
+
+    let mut plan = make_plan()?;
+    let mut last_full_scan = Instant::now();
+
+    loop {
+        execute_plan(plan, &solana_config)?;
+        plan = wait_for_next_plan(&mut watcher, &mut last_full_scan, config.interval)?;
+    }
+}
+
+/// Sleep until records change or a full scan is due,
+/// then plan the next round.
+///
+/// Full scans happen every `full_scan_interval`,
+/// in case a file event was missed or a step failed and needs retrying.
+fn wait_for_next_plan(
+    watcher: &mut DataWatcher,
+    last_full_scan: &mut Instant,
+    full_scan_interval: Duration,
+) -> Result<Plan> {
+    loop {
+        let now = Instant::now();
+        let next_full_scan = *last_full_scan + full_scan_interval;
+
+        if now >= next_full_scan {
+            *last_full_scan = now;
+            return make_plan();
+        }
+
+        let timeout = next_full_scan - now;
+        info!("waiting up to {} ms for changes", timeout.as_millis());
+
+        match watcher.wait_for_changes(timeout) {
+            Changes::Keys(keys) => return make_plan_for_keys(keys),
+            Changes::Rescan => {
+                *last_full_scan = Instant::now();
+                return make_plan();
+            }
+            Changes::None => {}
+        }
     }
 }
 
@@ -27,10 +95,35 @@ enum Step {
     UploadBlobToIpfs,
     UploadPlantToSolana,
     UploadClaimToSolana,
+    MintTreasureToSolana,
+    RetireTreasureToSolana,
 }
 
+impl Step {
+    /// The name used to label metrics
+    fn name(&self) -> &'static str {
+        match self {
+            Step::UploadBlobToIpfs => "upload_blob_to_ipfs",
+            Step::UploadPlantToSolana => "upload_plant_to_solana",
+            Step::UploadClaimToSolana => "upload_claim_to_solana",
+            Step::MintTreasureToSolana => "mint_treasure_to_solana",
+            Step::RetireTreasureToSolana => "retire_treasure_to_solana",
+        }
+    }
+}
+
+/// What happened when a step was executed
+enum StepOutcome {
+    /// The step's transaction was sent and its sync status recorded
+    Done,
+    /// The treasure wasn't in the sync status the step needs,
+    /// so nothing was sent
+    Skipped(Option<SyncStatus>),
+}
+
+/// Plan steps for every record in the data directory.
 fn make_plan() -> Result<Plan> {
-    info!("making new plan");
+    info!("making new plan from full scan");
 
     let statuses = io::get_all_sync_statuses()?;
 
@@ -42,44 +135,132 @@ fn make_plan() -> Result<Plan> {
         let pubkey = treasure.public_key;
         let status = statuses.get(&pubkey);
 
-        use io::PlantClaim::{Claim, Plant};
-        use io::SyncStatus::*;
-        use Step::*;
+        let has_wallet = matches!(event, io::PlantClaim::Claim) && claim_has_wallet(&pubkey);
+        plan_steps_for_event(&mut steps, pubkey, event, status, has_wallet);
+    }
 
-        match (event, status) {
-            (Plant, None) => {
-                steps.push((pubkey.clone(), UploadBlobToIpfs));
-                steps.push((pubkey, UploadPlantToSolana));
-            }
-            (Plant, Some(BlobSynced)) => {
-                steps.push((pubkey, UploadPlantToSolana));
+    metrics::set_backlog(steps.iter().map(|(_, step)| step.name()));
+
+    Ok(Plan { statuses, steps })
+}
+
+/// Plan steps for only the treasures reported by the file watcher.
+fn make_plan_for_keys(keys: BTreeSet<String>) -> Result<Plan> {
+    info!("making new plan for {} changed treasures", keys.len());
+
+    let mut statuses = HashMap::new();
+    let mut steps = Vec::new();
+
+    for pubkey in keys {
+        let status = io::get_sync_status(&pubkey).ok();
+
+        for event in io::get_treasure_events(&pubkey) {
+            let has_wallet = matches!(event, io::PlantClaim::Claim) && claim_has_wallet(&pubkey);
+            plan_steps_for_event(
+                &mut steps,
+                pubkey.clone(),
+                event,
+                status.as_ref(),
+                has_wallet,
+            );
+        }
+
+        if let Some(status) = status {
+            statuses.insert(pubkey, status);
+        }
+    }
+
+    metrics::add_backlog(steps.iter().map(|(_, step)| step.name()));
+
+    Ok(Plan { statuses, steps })
+}
+
+/// Plan the steps to sync one record of a treasure.
+///
+/// `has_wallet` is whether a claim is to be minted.
+fn plan_steps_for_event(
+    steps: &mut Vec<(String, Step)>,
+    pubkey: String,
+    event: io::PlantClaim,
+    status: Option<&SyncStatus>,
+    has_wallet: bool,
+) {
+    use io::PlantClaim::{Claim, Plant, Retire};
+    use io::SyncStatus::*;
+    use Step::*;
+
+    match (event, status) {
+        (Plant, None) => {
+            steps.push((pubkey.clone(), UploadBlobToIpfs));
+            steps.push((pubkey, UploadPlantToSolana));
+        }
+        (Plant, Some(BlobSynced)) => {
+            steps.push((pubkey, UploadPlantToSolana));
+        }
+        (Plant, Some(PlantSynced | ClaimSynced | MintSynced | RetireSynced)) => {
+            /* plant is synced */
+        }
+        (Claim, None) | (Claim, Some(BlobSynced | PlantSynced)) => {
+            steps.push((pubkey.clone(), UploadClaimToSolana));
+            if has_wallet {
+                steps.push((pubkey, MintTreasureToSolana));
             }
-            (Plant, Some(PlantSynced | ClaimSynced)) => { /* plant is synced */ }
-            (Claim, None) | (Claim, Some(BlobSynced | PlantSynced)) => {
-                steps.push((pubkey, UploadClaimToSolana));
+        }
+        (Claim, Some(ClaimSynced)) => {
+            if has_wallet {
+                steps.push((pubkey, MintTreasureToSolana));
             }
-            (Claim, Some(ClaimSynced)) => { /* claim is synced */ }
+        }
+        (Claim, Some(MintSynced | RetireSynced)) => { /* claim is synced and minted */ }
+        (Retire, Some(RetireSynced)) => { /* retirement is synced */ }
+        (Retire, _) => {
+            steps.push((pubkey, RetireTreasureToSolana));
         }
     }
+}
 
-    Ok(Plan { statuses, steps })
+/// Claims designating a wallet, or made by an account with a linked wallet,
+/// are minted as NFTs after they are synced.
+fn claim_has_wallet(pubkey: &str) -> bool {
+    io::get_claim_wallet(pubkey)
+        .map(|wallet| wallet.is_some())
+        .unwrap_or(false)
 }
 
-fn execute_plan(plan: Plan) -> Result<()> {
+/// Retiring is the last thing that happens to a treasure,
+/// so it is only synced after the plant, and any claim and mint.
+fn ready_to_retire(pubkey: &str, status: Option<SyncStatus>) -> bool {
+    match status {
+        Some(SyncStatus::PlantSynced) => io::get_claim(pubkey).is_err(),
+        Some(SyncStatus::ClaimSynced) => !claim_has_wallet(pubkey),
+        Some(SyncStatus::MintSynced) => true,
+        _ => false,
+    }
+}
+
+fn execute_plan(plan: Plan, config: &solana::Config) -> Result<()> {
+    update_payer_balance(config);
+
+    if plan.steps.is_empty() {
+        info!("nothing to sync");
+        metrics::record_round(0);
+        return Ok(());
+    }
+
     info!("executing plan with {} steps", plan.steps.len());
 
-    let config = solana::load_config()?;
-    let client = solana::connect(&config)?;
-    let program_keypair = solana::get_program_keypair(&client)?;
-    let program_instance_account =
-        solana::get_program_instance_account(&client, &config.keypair, &program_keypair)?;
+    let client = solana::connect(config)?;
+    solana::check_program(&client, config)?;
+    let program_instance_account = solana::get_program_instance_account(&client, config)?;
 
     let mut statuses = plan.statuses;
+    let mut failed_steps = 0;
+    let mut skipped_steps = 0;
 
     for (pubkey, step) in plan.steps {
         info!("executing step {:?} for {}", step, pubkey);
 
-        let r = || -> Result<()> {
+        let r = || -> Result<StepOutcome> {
             let status = statuses.get(&pubkey).cloned();
             match step {
                 Step::UploadBlobToIpfs => {
@@ -88,58 +269,266 @@ fn execute_plan(plan: Plan) -> Result<()> {
                         io::record_sync_status(&pubkey, SyncStatus::BlobSynced)?;
                         statuses.insert(pubkey, SyncStatus::BlobSynced);
                     } else {
-                        warn!("unexpected sync status: {:?}", status);
+                        return Ok(StepOutcome::Skipped(status));
                     }
                 }
                 Step::UploadPlantToSolana => {
                     if status == Some(SyncStatus::BlobSynced) {
-                        solana::upload_plant(
-                            &pubkey,
-                            &config,
-                            &client,
-                            &program_keypair,
-                            &program_instance_account,
-                        )?;
+                        solana::upload_plant(&pubkey, config, &client, &program_instance_account)?;
                         io::record_sync_status(&pubkey, SyncStatus::PlantSynced)?;
                         statuses.insert(pubkey, SyncStatus::PlantSynced);
                     } else {
-                        warn!("unexpected sync status: {:?}", status);
+                        return Ok(StepOutcome::Skipped(status));
                     }
                 }
                 Step::UploadClaimToSolana => {
                     if status == Some(SyncStatus::PlantSynced) {
-                        solana::upload_claim(
-                            &pubkey,
-                            &config,
-                            &client,
-                            &program_keypair,
-                            &program_instance_account,
-                        )?;
+                        solana::upload_claim(&pubkey, config, &client, &program_instance_account)?;
                         io::record_sync_status(&pubkey, SyncStatus::ClaimSynced)?;
                         statuses.insert(pubkey, SyncStatus::ClaimSynced);
                     } else {
-                        warn!("unexpected sync status: {:?}", status);
+                        return Ok(StepOutcome::Skipped(status));
+                    }
+                }
+                Step::MintTreasureToSolana => {
+                    if status == Some(SyncStatus::ClaimSynced) {
+                        solana::mint_treasure(&pubkey, config, &client, &program_instance_account)?;
+                        io::record_sync_status(&pubkey, SyncStatus::MintSynced)?;
+                        statuses.insert(pubkey, SyncStatus::MintSynced);
+                    } else {
+                        return Ok(StepOutcome::Skipped(status));
+                    }
+                }
+                Step::RetireTreasureToSolana => {
+                    if ready_to_retire(&pubkey, status) {
+                        solana::upload_retire(&pubkey, config, &client, &program_instance_account)?;
+                        io::record_sync_status(&pubkey, SyncStatus::RetireSynced)?;
+                        statuses.insert(pubkey, SyncStatus::RetireSynced);
+                    } else {
+                        return Ok(StepOutcome::Skipped(status));
                     }
                 }
             }
 
-            Ok(())
+            Ok(StepOutcome::Done)
         }();
 
-        if let Err(e) = r {
-            error!("{}", e);
-        } else {
-            // info!("successfully executed step {:?} for {}", step, pubkey);
-            info!("successfully executed step {:?}", step);
+        metrics::record_step(
+            step.name(),
+            match r {
+                Ok(StepOutcome::Done) => metrics::StepResult::Success,
+                Ok(StepOutcome::Skipped(_)) => metrics::StepResult::Skipped,
+                Err(_) => metrics::StepResult::Failure,
+            },
+        );
+
+        match r {
+            Ok(StepOutcome::Done) => {
+                // info!("successfully executed step {:?} for {}", step, pubkey);
+                info!("successfully executed step {:?}", step);
+            }
+            Ok(StepOutcome::Skipped(status)) => {
+                warn!(
+                    "skipped step {:?}, unexpected sync status: {:?}",
+                    step, status
+                );
+                skipped_steps += 1;
+            }
+            Err(e) => {
+                error!("{}", e);
+                failed_steps += 1;
+            }
+        }
+    }
+
+    if skipped_steps > 0 {
+        warn!(
+            "skipped {} steps, they will be retried on the next full scan",
+            skipped_steps
+        );
+    }
+
+    metrics::record_round(failed_steps + skipped_steps);
+
+    Ok(())
+}
+
+fn update_payer_balance(config: &solana::Config) {
+    let client = solana::new_client(config);
+
+    match solana::get_payer_balance(config, &client) {
+        Ok(balance) => {
+            info!("payer balance: {} lamports", balance);
+            metrics::set_payer_balance(balance);
         }
+        Err(e) => warn!("unable to get payer balance: {}", e),
+    }
+}
+
+/// Print every step of the plan, with the instruction it would send
+/// and an estimate of its fee, without sending any transactions.
+///
+/// Fees are only estimated if the RPC node is reachable.
+fn print_plan(plan: &Plan, config: &solana::Config) -> Result<()> {
+    let client = solana::new_client(config);
+    let program_instance_account = solana::program_instance_pubkey(config)?;
+
+    println!("program id: {}", config.program_id);
+    println!("program instance account: {}", program_instance_account);
+    println!("payer: {}", config.keypair.pubkey());
+    println!("{} steps", plan.steps.len());
+
+    let mut total_fee = Some(0);
+
+    for (pubkey, step) in &plan.steps {
+        let status = plan.statuses.get(pubkey);
+
+        println!();
+        println!("{} {:?}", pubkey, step);
+        println!("    current status: {:?}", status);
+
+        let request = match step {
+            Step::UploadBlobToIpfs => {
+                println!("    no transaction");
+                continue;
+            }
+            Step::UploadPlantToSolana => solana::plant_request(pubkey, &config.base_url),
+            Step::UploadClaimToSolana => solana::claim_request(pubkey),
+            Step::RetireTreasureToSolana => solana::retire_request(pubkey),
+            Step::MintTreasureToSolana => {
+                match solana::mint_instruction(pubkey, config, &program_instance_account) {
+                    Ok(Some(inst)) => {
+                        print_instruction(&inst, config, &client, &mut total_fee);
+                    }
+                    Ok(None) => println!("    no wallet to mint to"),
+                    Err(e) => println!("    unable to build instruction: {}", e),
+                }
+                continue;
+            }
+        };
+
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                println!("    unable to build request: {}", e);
+                continue;
+            }
+        };
+
+        let inst =
+            solana::create_instruction(&request, &config.program_id, &program_instance_account)?;
+
+        println!("    request: {:?}", request);
+        print_instruction(&inst, config, &client, &mut total_fee);
+    }
+
+    println!();
+    match total_fee {
+        Some(fee) => println!("total estimated fee: {} lamports", fee),
+        None => println!("total estimated fee: unknown"),
     }
 
     Ok(())
 }
 
-fn wait_for_next_round() {
-    let delay_ms = 1000;
-    info!("sleeping for {} ms", delay_ms);
-    #[allow(deprecated)]
-    thread::sleep_ms(delay_ms);
+/// Print an instruction's data and estimated fee,
+/// adding the fee to the total if it is known.
+fn print_instruction(
+    inst: &Instruction,
+    config: &solana::Config,
+    client: &RpcClient,
+    total_fee: &mut Option<u64>,
+) {
+    println!(
+        "    instruction data ({} bytes): {}",
+        inst.data.len(),
+        to_hex(&inst.data)
+    );
+
+    match solana::estimate_fee(inst, config, client) {
+        Ok(fee) => {
+            println!("    estimated fee: {} lamports", fee);
+            *total_fee = total_fee.map(|total| total + fee);
+        }
+        Err(e) => {
+            println!("    estimated fee: unknown ({})", e);
+            *total_fee = None;
+        }
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use io::PlantClaim::{Claim, Plant, Retire};
+    use io::SyncStatus::*;
+
+    fn plan(
+        event: io::PlantClaim,
+        status: Option<SyncStatus>,
+        has_wallet: bool,
+    ) -> Vec<&'static str> {
+        let mut steps = Vec::new();
+        plan_steps_for_event(
+            &mut steps,
+            "gtp1".to_string(),
+            event,
+            status.as_ref(),
+            has_wallet,
+        );
+        assert!(steps.iter().all(|(pubkey, _)| pubkey == "gtp1"));
+        steps.iter().map(|(_, step)| step.name()).collect()
+    }
+
+    #[test]
+    fn plan_plant() {
+        assert_eq!(
+            plan(Plant, None, false),
+            vec!["upload_blob_to_ipfs", "upload_plant_to_solana"]
+        );
+        assert_eq!(
+            plan(Plant, Some(BlobSynced), false),
+            vec!["upload_plant_to_solana"]
+        );
+        for status in vec![PlantSynced, ClaimSynced, MintSynced, RetireSynced] {
+            assert!(plan(Plant, Some(status), false).is_empty());
+        }
+    }
+
+    #[test]
+    fn plan_claim() {
+        for status in vec![None, Some(BlobSynced), Some(PlantSynced)] {
+            assert_eq!(
+                plan(Claim, status.clone(), false),
+                vec!["upload_claim_to_solana"]
+            );
+            assert_eq!(
+                plan(Claim, status, true),
+                vec!["upload_claim_to_solana", "mint_treasure_to_solana"]
+            );
+        }
+
+        assert!(plan(Claim, Some(ClaimSynced), false).is_empty());
+        assert_eq!(
+            plan(Claim, Some(ClaimSynced), true),
+            vec!["mint_treasure_to_solana"]
+        );
+        assert!(plan(Claim, Some(MintSynced), true).is_empty());
+        assert!(plan(Claim, Some(RetireSynced), true).is_empty());
+    }
+
+    #[test]
+    fn plan_retire() {
+        for status in vec![None, Some(PlantSynced), Some(ClaimSynced), Some(MintSynced)] {
+            assert_eq!(
+                plan(Retire, status, false),
+                vec!["retire_treasure_to_solana"]
+            );
+        }
+        assert!(plan(Retire, Some(RetireSynced), false).is_empty());
+    }
 }
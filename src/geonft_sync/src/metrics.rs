@@ -0,0 +1,195 @@
+//! Prometheus metrics and a health check, served over HTTP
+//!
+//! - `/metrics` exposes the metrics in the Prometheus text format.
+//! - `/healthz` returns 200 if a round of syncing completed recently
+//!   and the payer can still afford transactions, 503 otherwise.
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Response, Server};
+
+lazy_static! {
+    static ref BACKLOG_STEPS: IntGaugeVec = register_int_gauge_vec!(
+        "geonft_sync_backlog_steps",
+        "Planned steps not yet executed successfully",
+        &["step"]
+    )
+    .expect("metric");
+    static ref STEPS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "geonft_sync_steps_total",
+        "Executed steps",
+        &["step", "result"]
+    )
+    .expect("metric");
+    static ref RPC_DURATION_SECONDS: HistogramVec = register_histogram_vec!(
+        "geonft_sync_rpc_duration_seconds",
+        "Latency of Solana RPC calls",
+        &["call"]
+    )
+    .expect("metric");
+    static ref PAYER_BALANCE_LAMPORTS: IntGauge = register_int_gauge!(
+        "geonft_sync_payer_balance_lamports",
+        "Balance of the account paying for transactions"
+    )
+    .expect("metric");
+    static ref LAST_ROUND_TIMESTAMP_SECONDS: IntGauge = register_int_gauge!(
+        "geonft_sync_last_round_timestamp_seconds",
+        "Unix time the last round of syncing completed"
+    )
+    .expect("metric");
+    static ref LAST_SUCCESSFUL_ROUND_TIMESTAMP_SECONDS: IntGauge = register_int_gauge!(
+        "geonft_sync_last_successful_round_timestamp_seconds",
+        "Unix time the last round of syncing completed with no failed or skipped steps"
+    )
+    .expect("metric");
+}
+
+/// Thresholds for `/healthz`
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    /// Unhealthy if no round has completed within this time
+    pub max_round_age: Duration,
+    /// Unhealthy if the payer balance drops below this many lamports
+    pub min_payer_balance: u64,
+}
+
+/// Serve `/metrics` and `/healthz` on a background thread.
+pub fn serve(addr: SocketAddr, health: HealthConfig) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow!("unable to serve metrics: {}", e))?;
+
+    info!("serving metrics on http://{}", addr);
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/metrics" => metrics_response(),
+                "/healthz" => health_response(&health),
+                _ => Response::from_string("not found").with_status_code(404),
+            };
+
+            if let Err(e) = request.respond(response) {
+                warn!("unable to respond to metrics request: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn metrics_response() -> Response<std::io::Cursor<Vec<u8>>> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        return Response::from_string(format!("{}", e)).with_status_code(500);
+    }
+
+    let content_type =
+        Header::from_bytes(&b"Content-Type"[..], encoder.format_type().as_bytes()).expect("header");
+
+    Response::from_data(buffer).with_header(content_type)
+}
+
+fn health_response(health: &HealthConfig) -> Response<std::io::Cursor<Vec<u8>>> {
+    match check_health(health) {
+        Ok(()) => Response::from_string("ok"),
+        Err(e) => Response::from_string(format!("{}", e)).with_status_code(503),
+    }
+}
+
+fn check_health(health: &HealthConfig) -> Result<()> {
+    let last_round = LAST_ROUND_TIMESTAMP_SECONDS.get();
+
+    if last_round == 0 {
+        return Err(anyhow!("no round has completed yet"));
+    }
+
+    let round_age = unix_time() - last_round;
+    if round_age > health.max_round_age.as_secs() as i64 {
+        return Err(anyhow!("last round completed {} s ago", round_age));
+    }
+
+    let balance = PAYER_BALANCE_LAMPORTS.get();
+    if balance < health.min_payer_balance as i64 {
+        return Err(anyhow!("payer balance is {} lamports", balance));
+    }
+
+    Ok(())
+}
+
+/// Replace the backlog with the steps of a plan made from a full scan.
+pub fn set_backlog<'a>(steps: impl Iterator<Item = &'a str>) {
+    BACKLOG_STEPS.reset();
+    add_backlog(steps);
+}
+
+/// Add the steps of a plan made from file events to the backlog.
+pub fn add_backlog<'a>(steps: impl Iterator<Item = &'a str>) {
+    for step in steps {
+        BACKLOG_STEPS.with_label_values(&[step]).inc();
+    }
+}
+
+/// The result label of an executed step
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StepResult {
+    Success,
+    /// Not attempted because the treasure wasn't ready,
+    /// so the step stays in the backlog
+    Skipped,
+    Failure,
+}
+
+pub fn record_step(step: &str, result: StepResult) {
+    let label = match result {
+        StepResult::Success => "success",
+        StepResult::Skipped => "skipped",
+        StepResult::Failure => "failure",
+    };
+    STEPS_TOTAL.with_label_values(&[step, label]).inc();
+
+    if result == StepResult::Success {
+        BACKLOG_STEPS.with_label_values(&[step]).dec();
+    }
+}
+
+/// Record a completed round.
+///
+/// A round with failed or skipped steps isn't successful.
+pub fn record_round(unfinished_steps: usize) {
+    let now = unix_time();
+    LAST_ROUND_TIMESTAMP_SECONDS.set(now);
+
+    if unfinished_steps == 0 {
+        LAST_SUCCESSFUL_ROUND_TIMESTAMP_SECONDS.set(now);
+    }
+}
+
+pub fn set_payer_balance(lamports: u64) {
+    PAYER_BALANCE_LAMPORTS.set(lamports as i64);
+}
+
+/// Run an RPC call, recording how long it took.
+pub fn time_rpc<T>(call: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let r = f();
+    RPC_DURATION_SECONDS
+        .with_label_values(&[call])
+        .observe(start.elapsed().as_secs_f64());
+    r
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
@@ -3,40 +3,64 @@ use log::info;
 use std::convert::TryInto;
 
 use geonft_nostd::crypto;
-use geonft_request::{ClaimRequestSolana, GeonftRequestSolana, PlantRequestSolana};
+use geonft_request::{
+    ClaimRequestSolana, GeonftRequestSolana, PlantRequestSolana, RetireRequestSolana,
+};
 use geonft_shared::io;
+use geonft_solana::{CURRENT_LAYOUT, UNINITIALIZED_LAYOUT};
+
+use crate::config;
+use crate::metrics;
 
 use borsh::ser::BorshSerialize;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::message::Message;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
 use solana_sdk::system_instruction;
 use solana_sdk::transaction::Transaction;
+use solana_sdk::{bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable};
 
 pub struct Config {
     pub json_rpc_url: String,
     pub keypair: Keypair,
+    pub program_id: Pubkey,
+    pub base_url: String,
+    pub instance_seed: String,
+    pub instance_size: usize,
+    pub mint_metadata: bool,
 }
 
-pub fn load_config() -> Result<Config> {
-    let config_file = solana_cli_config::CONFIG_FILE
-        .as_ref()
-        .ok_or_else(|| anyhow!("config file path"))?;
-    let cli_config = solana_cli_config::Config::load(&config_file)?;
-    let json_rpc_url = cli_config.json_rpc_url;
-    let keypair = read_keypair_file(&cli_config.keypair_path).map_err(|e| anyhow!("{}", e))?;
+pub fn load_config(config: &config::Config) -> Result<Config> {
+    info!(
+        "loading payer keypair from {}",
+        config.payer_keypair.display()
+    );
+
+    let keypair = read_keypair_file(&config.payer_keypair)
+        .map_err(|e| anyhow!("{}", e))
+        .context("unable to load payer keypair")?;
+
+    let program_id = config.program_id;
+
+    info!("program id: {}", program_id);
+
     Ok(Config {
-        json_rpc_url,
+        json_rpc_url: config.rpc_url.clone(),
         keypair,
+        program_id,
+        base_url: config.base_url.clone(),
+        instance_seed: config.instance_seed.clone(),
+        instance_size: config.instance_size,
+        mint_metadata: config.mint_metadata,
     })
 }
 
 pub fn connect(config: &Config) -> Result<RpcClient> {
     info!("connecting to solana node at {}", config.json_rpc_url);
-    let client =
-        RpcClient::new_with_commitment(config.json_rpc_url.clone(), CommitmentConfig::confirmed());
+    let client = new_client(config);
 
     let version = client.get_version()?;
     info!("RPC version: {:?}", version);
@@ -50,26 +74,14 @@ pub fn connect(config: &Config) -> Result<RpcClient> {
     Ok(client)
 }
 
-static DEPLOY_PATH: &str = "target/deploy";
-static PROGRAM_KEYPAIR_PATH: &str = "geonft_solana-keypair.json";
-
-pub fn get_program_keypair(client: &RpcClient) -> Result<Keypair> {
-    let manifest_dir = env!("CARGO_MANIFEST_DIR");
-    let deploy_path = format!("{}/../../{}", manifest_dir, DEPLOY_PATH);
-    let program_keypair_path = format!("{}/{}", deploy_path, PROGRAM_KEYPAIR_PATH);
-
-    info!("loading program keypair from {}", program_keypair_path);
-
-    let program_keypair = read_keypair_file(&program_keypair_path)
-        .map_err(|e| anyhow!("{}", e))
-        .context("unable to load program keypair")?;
-
-    let program_id = program_keypair.pubkey();
-
-    info!("program id: {}", program_id);
+/// Create a client without checking the node is reachable.
+pub fn new_client(config: &Config) -> RpcClient {
+    RpcClient::new_with_commitment(config.json_rpc_url.clone(), CommitmentConfig::confirmed())
+}
 
+pub fn check_program(client: &RpcClient, config: &Config) -> Result<()> {
     let account = client
-        .get_account(&program_id)
+        .get_account(&config.program_id)
         .context("unable to get program account")?;
 
     info!("program account: {:?}", account);
@@ -78,18 +90,37 @@ pub fn get_program_keypair(client: &RpcClient) -> Result<Keypair> {
         bail!("solana account not executable");
     }
 
-    Ok(program_keypair)
+    let bpf_loaders = [
+        bpf_loader::id(),
+        bpf_loader_deprecated::id(),
+        bpf_loader_upgradeable::id(),
+    ];
+
+    if !bpf_loaders.contains(&account.owner) {
+        bail!(
+            "solana account not owned by a BPF loader, owner is {}",
+            account.owner
+        );
+    }
+
+    Ok(())
 }
 
-pub fn get_program_instance_account(
-    client: &RpcClient,
-    payer_account: &Keypair,
-    program_keypair: &Keypair,
-) -> Result<Pubkey> {
-    static SEED: &str = "geonft";
+/// The address of the program instance account, derived from the payer.
+pub fn program_instance_pubkey(config: &Config) -> Result<Pubkey> {
+    let pubkey = Pubkey::create_with_seed(
+        &config.keypair.pubkey(),
+        &config.instance_seed,
+        &config.program_id,
+    )?;
+    Ok(pubkey)
+}
 
-    let pubkey =
-        Pubkey::create_with_seed(&payer_account.pubkey(), SEED, &program_keypair.pubkey())?;
+pub fn get_program_instance_account(client: &RpcClient, config: &Config) -> Result<Pubkey> {
+    let payer_account = &config.keypair;
+    let seed = &config.instance_seed;
+
+    let pubkey = program_instance_pubkey(config)?;
 
     info!("program account pubkey: {}", pubkey);
 
@@ -98,7 +129,7 @@ pub fn get_program_instance_account(
     if account.is_err() {
         info!("creating program instance at {}", pubkey);
 
-        let contract_size = get_contract_size(client)?;
+        let contract_size = config.instance_size;
         info!("contract size: {}", contract_size);
         let lamports = client.get_minimum_balance_for_rent_exemption(contract_size)?;
         info!("minimim balance for rent exemption: {}", lamports);
@@ -107,10 +138,10 @@ pub fn get_program_instance_account(
             &payer_account.pubkey(),
             &pubkey,
             &payer_account.pubkey(),
-            SEED,
+            seed,
             lamports,
             contract_size.try_into().expect("u64"),
-            &program_keypair.pubkey(),
+            &config.program_id,
         );
 
         let recent_blockhash = client.get_recent_blockhash()?.0;
@@ -135,21 +166,87 @@ pub fn get_program_instance_account(
 
     info!("program instance account: {:?}", account);
 
-    Ok(pubkey)
-}
+    let layout = account
+        .data
+        .first()
+        .copied()
+        .unwrap_or(UNINITIALIZED_LAYOUT);
+    if layout != UNINITIALIZED_LAYOUT && layout != CURRENT_LAYOUT {
+        info!(
+            "upgrading program instance from layout {} to {}",
+            layout, CURRENT_LAYOUT
+        );
+        let inst = create_instruction(
+            &GeonftRequestSolana::UpgradeInstance,
+            &config.program_id,
+            &pubkey,
+        )?;
+        let sig = send_instruction(inst, config, client)?;
+        info!("upgrade sig: {}", &sig);
+    }
 
-fn get_contract_size(_client: &RpcClient) -> Result<usize> {
-    // TODO
-    Ok(10_000)
+    Ok(pubkey)
 }
 
 pub fn upload_plant(
     plant_key: &str,
     config: &Config,
     client: &RpcClient,
-    program: &Keypair,
     program_account: &Pubkey,
 ) -> Result<()> {
+    let plant_request = plant_request(plant_key, &config.base_url)?;
+    let inst = create_instruction(&plant_request, &config.program_id, program_account)?;
+    let sig = send_instruction(inst, config, client)?;
+
+    info!("plant sig: {}", &sig);
+    Ok(())
+}
+
+pub fn upload_claim(
+    claim_key: &str,
+    config: &Config,
+    client: &RpcClient,
+    program_account: &Pubkey,
+) -> Result<()> {
+    let claim_request = claim_request(claim_key)?;
+    let inst = create_instruction(&claim_request, &config.program_id, program_account)?;
+    let sig = send_instruction(inst, config, client)?;
+
+    info!("claim sig: {}", &sig);
+    Ok(())
+}
+
+pub fn upload_retire(
+    retire_key: &str,
+    config: &Config,
+    client: &RpcClient,
+    program_account: &Pubkey,
+) -> Result<()> {
+    let retire_request = retire_request(retire_key)?;
+    let inst = create_instruction(&retire_request, &config.program_id, program_account)?;
+    let sig = send_instruction(inst, config, client)?;
+
+    info!("retire sig: {}", &sig);
+    Ok(())
+}
+
+/// Mint a claimed treasure to the wallet designated by its claim,
+/// or linked to its claimer.
+pub fn mint_treasure(
+    claim_key: &str,
+    config: &Config,
+    client: &RpcClient,
+    program_account: &Pubkey,
+) -> Result<()> {
+    let inst = mint_instruction(claim_key, config, program_account)?
+        .ok_or_else(|| anyhow!("claim has no wallet to mint to"))?;
+    let sig = send_instruction(inst, config, client)?;
+
+    info!("mint sig: {}", &sig);
+    Ok(())
+}
+
+pub fn plant_request(plant_key: &str, base_url: &str) -> Result<GeonftRequestSolana> {
     let plant_request = io::get_plant(plant_key)?;
     let hash = crypto::get_hash(&plant_request.image)?;
     let plant_request = PlantRequestSolana {
@@ -160,24 +257,18 @@ pub fn upload_plant(
         treasure_public_key: crypto::decode_treasure_public_key_to_bytes(
             &plant_request.treasure_public_key,
         )?,
+        metadata_uri: metadata_uri(plant_key, base_url),
+        not_before: plant_request.not_before,
+        not_after: plant_request.not_after,
     };
-    let inst = create_plant_instruction(plant_request, &program.pubkey(), program_account)?;
-    let mut tx = Transaction::new_with_payer(&[inst], Some(&config.keypair.pubkey()));
-    let blockhash = client.get_recent_blockhash()?.0;
-    tx.try_sign(&[&config.keypair], blockhash)?;
-    let sig = client.send_and_confirm_transaction_with_spinner(&tx)?;
+    Ok(GeonftRequestSolana::PlantTreasure(plant_request))
+}
 
-    info!("plant sig: {}", &sig);
-    Ok(())
+pub fn metadata_uri(plant_key: &str, base_url: &str) -> String {
+    format!("{}/api/treasures/{}/metadata.json", base_url, plant_key)
 }
 
-pub fn upload_claim(
-    claim_key: &str,
-    config: &Config,
-    client: &RpcClient,
-    program: &Keypair,
-    program_account: &Pubkey,
-) -> Result<()> {
+pub fn claim_request(claim_key: &str) -> Result<GeonftRequestSolana> {
     let claim_request = io::get_claim(claim_key)?;
     let claim_request = ClaimRequestSolana {
         account_public_key: crypto::decode_account_public_key_to_bytes(
@@ -186,25 +277,60 @@ pub fn upload_claim(
         treasure_public_key: crypto::decode_treasure_public_key_to_bytes(
             &claim_request.treasure_public_key,
         )?,
+        wallet: match io::get_claim_wallet(claim_key)? {
+            Some(wallet) => Some(crypto::decode_wallet_address(&wallet)?.to_vec()),
+            None => None,
+        },
+        claimed_at: claim_request.timestamp.map(|timestamp| timestamp as i64),
     };
+    Ok(GeonftRequestSolana::ClaimTreasure(claim_request))
+}
 
-    let inst = create_claim_instruction(claim_request, &program.pubkey(), program_account)?;
+pub fn retire_request(retire_key: &str) -> Result<GeonftRequestSolana> {
+    let retire_request = io::get_retire(retire_key)?;
+    let retire_request = RetireRequestSolana {
+        account_public_key: crypto::decode_account_public_key_to_bytes(
+            &retire_request.account_public_key,
+        )?,
+        treasure_public_key: crypto::decode_treasure_public_key_to_bytes(
+            &retire_request.treasure_public_key,
+        )?,
+    };
+    Ok(GeonftRequestSolana::RetireTreasure(retire_request))
+}
 
-    let mut tx = Transaction::new_with_payer(&[inst], Some(&config.keypair.pubkey()));
-    let blockhash = client.get_recent_blockhash()?.0;
-    tx.try_sign(&[&config.keypair], blockhash)?;
-    let sig = client.send_and_confirm_transaction_with_spinner(&tx)?;
+/// The instruction minting a claimed treasure,
+/// or `None` if there is no wallet to mint it to.
+pub fn mint_instruction(
+    claim_key: &str,
+    config: &Config,
+    program_instance: &Pubkey,
+) -> Result<Option<Instruction>> {
+    let claim_request = io::get_claim(claim_key)?;
+    let wallet = match io::get_claim_wallet(claim_key)? {
+        Some(wallet) => Pubkey::new(&crypto::decode_wallet_address(&wallet)?),
+        None => return Ok(None),
+    };
 
-    info!("claim sig: {}", &sig);
-    Ok(())
+    let inst = geonft_solana::mint_treasure_instruction(
+        &config.program_id,
+        program_instance,
+        &config.keypair.pubkey(),
+        &wallet,
+        crypto::decode_treasure_public_key_to_bytes(&claim_request.treasure_public_key)?,
+        format!("Treasure {}", geonft_nostd::abbrev_pubkey(claim_key)),
+        config.mint_metadata,
+    )?;
+
+    Ok(Some(inst))
 }
 
-fn create_plant_instruction(
-    plant_request: PlantRequestSolana,
+pub fn create_instruction(
+    request: &GeonftRequestSolana,
     program_id: &Pubkey,
     program_instance: &Pubkey,
 ) -> Result<Instruction> {
-    let data = GeonftRequestSolana::PlantTreasure(plant_request).try_to_vec()?;
+    let data = request.try_to_vec()?;
     Ok(Instruction {
         program_id: *program_id,
         accounts: vec![AccountMeta::new(*program_instance, false)],
@@ -212,15 +338,26 @@ fn create_plant_instruction(
     })
 }
 
-fn create_claim_instruction(
-    claim_request: ClaimRequestSolana,
-    program_id: &Pubkey,
-    program_instance: &Pubkey,
-) -> Result<Instruction> {
-    let data = GeonftRequestSolana::ClaimTreasure(claim_request).try_to_vec()?;
-    Ok(Instruction {
-        program_id: *program_id,
-        accounts: vec![AccountMeta::new(*program_instance, false)],
-        data,
-    })
+fn send_instruction(inst: Instruction, config: &Config, client: &RpcClient) -> Result<Signature> {
+    let mut tx = Transaction::new_with_payer(&[inst], Some(&config.keypair.pubkey()));
+    let blockhash = metrics::time_rpc("get_recent_blockhash", || client.get_recent_blockhash())?.0;
+    tx.try_sign(&[&config.keypair], blockhash)?;
+    let sig = metrics::time_rpc("send_and_confirm_transaction", || {
+        client.send_and_confirm_transaction_with_spinner(&tx)
+    })?;
+    Ok(sig)
+}
+
+pub fn get_payer_balance(config: &Config, client: &RpcClient) -> Result<u64> {
+    let balance = metrics::time_rpc("get_balance", || {
+        client.get_balance(&config.keypair.pubkey())
+    })?;
+    Ok(balance)
+}
+
+/// The fee in lamports the payer would be charged to send `inst`.
+pub fn estimate_fee(inst: &Instruction, config: &Config, client: &RpcClient) -> Result<u64> {
+    let (_, fee_calculator) = client.get_recent_blockhash()?;
+    let message = Message::new(&[inst.clone()], Some(&config.keypair.pubkey()));
+    Ok(fee_calculator.calculate_fee(&message))
 }
@@ -0,0 +1,126 @@
+//! Watching the data directory for new plant and claim records
+//!
+//! The web server writes one file per record,
+//! named by the treasure public key,
+//! so a file event tells us exactly which treasure needs syncing.
+
+use anyhow::Result;
+use log::{info, warn};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use geonft_shared::io;
+
+/// How long to let writes to a file settle before reporting it.
+const DEBOUNCE_MS: u64 = 500;
+
+pub struct DataWatcher {
+    // Kept alive so events keep flowing
+    _watcher: RecommendedWatcher,
+    rx: Receiver<DebouncedEvent>,
+}
+
+/// What changed while we were waiting
+pub enum Changes {
+    /// Nothing happened before the timeout
+    None,
+    /// Records for these treasure keys were created or modified
+    Keys(BTreeSet<String>),
+    /// The watcher lost track of events and everything must be rescanned
+    Rescan,
+}
+
+impl DataWatcher {
+    pub fn new() -> Result<DataWatcher> {
+        let (watcher, rx) = watch_data_dirs()?;
+
+        Ok(DataWatcher {
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    /// Block until some records change or `timeout` elapses.
+    ///
+    /// Once the first event arrives any others that are already
+    /// queued are collected too, so a burst of writes
+    /// results in a single round of syncing.
+    ///
+    /// If the watcher has died it is restarted,
+    /// and if that fails this sleeps for `timeout`
+    /// so the caller's rescans don't spin.
+    pub fn wait_for_changes(&mut self, timeout: Duration) -> Changes {
+        let first = match self.rx.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Changes::None,
+            Err(RecvTimeoutError::Disconnected) => {
+                warn!("file watcher disconnected, restarting it");
+                match watch_data_dirs() {
+                    Ok((watcher, rx)) => {
+                        self._watcher = watcher;
+                        self.rx = rx;
+                    }
+                    Err(e) => {
+                        warn!("unable to restart file watcher: {}", e);
+                        thread::sleep(timeout);
+                    }
+                }
+                return Changes::Rescan;
+            }
+        };
+
+        let mut keys = BTreeSet::new();
+
+        for event in Some(first).into_iter().chain(self.rx.try_iter()) {
+            match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => {
+                    if let Some(key) = key_from_path(&path) {
+                        keys.insert(key);
+                    }
+                }
+                DebouncedEvent::Rescan => return Changes::Rescan,
+                DebouncedEvent::Error(e, path) => {
+                    warn!("file watcher error for {:?}: {}", path, e);
+                    return Changes::Rescan;
+                }
+                DebouncedEvent::NoticeWrite(_)
+                | DebouncedEvent::NoticeRemove(_)
+                | DebouncedEvent::Chmod(_)
+                | DebouncedEvent::Remove(_) => {}
+            }
+        }
+
+        if keys.is_empty() {
+            Changes::None
+        } else {
+            Changes::Keys(keys)
+        }
+    }
+}
+
+fn watch_data_dirs() -> Result<(RecommendedWatcher, Receiver<DebouncedEvent>)> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::watcher(tx, Duration::from_millis(DEBOUNCE_MS))?;
+
+    // Sync statuses are written by us, so only plants, claims and retirements are watched.
+    for dir in &[io::PLANT_DIR, io::CLAIM_DIR, io::RETIRE_DIR] {
+        fs::create_dir_all(dir)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        info!("watching {} for changes", dir);
+    }
+
+    Ok((watcher, rx))
+}
+
+fn key_from_path(path: &Path) -> Option<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+}
@@ -0,0 +1,80 @@
+//! Create a batch of treasure keys as printable SVG pages.
+//!
+//! ```
+//! cargo run -p geonft_wasm --example qrcode_sheet -- <count> [label]
+//! ```
+//!
+//! The QR codes link to `https://treasuretree.org`
+//! unless the `TREASURETREE_BASE_URL` environment variable
+//! names another site.
+//!
+//! If `TREASURETREE_PLANTER_SEED` is set to a `gps1...` planter seed,
+//! the keys are derived from it, starting at index
+//! `TREASURETREE_START_INDEX`, or 0.
+//! Running again with the same seed and index re-creates the same sheet.
+//!
+//! Writes `treasure-sheet-<n>.svg` to the current directory,
+//! and prints the new treasure secret keys, one per line,
+//! so they can be kept as a backup.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+
+static DEFAULT_BASE_URL: &str = "https://treasuretree.org";
+
+#[derive(Deserialize)]
+struct Code {
+    secret_key: String,
+}
+
+#[derive(Deserialize)]
+struct Sheet {
+    codes: Vec<Code>,
+    pages: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let count: usize = args
+        .next()
+        .ok_or_else(|| anyhow!("usage: qrcode_sheet <count> [label]"))?
+        .parse()
+        .context("count must be a number")?;
+    let label = args.next();
+    let base_url =
+        env::var("TREASURETREE_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+
+    let sheet = match env::var("TREASURETREE_PLANTER_SEED") {
+        Ok(seed) => {
+            let start_index = match env::var("TREASURETREE_START_INDEX") {
+                Ok(index) => index.parse().context("start index must be a number")?,
+                Err(_) => 0,
+            };
+            geonft_wasm::create_seeded_qrcode_sheet(
+                &seed,
+                start_index,
+                count,
+                label,
+                &base_url,
+                None,
+            )
+        }
+        Err(_) => geonft_wasm::create_qrcode_sheet(count, label, &base_url, None),
+    };
+    let sheet = sheet.ok_or_else(|| anyhow!("unable to create {} QR codes", count))?;
+    let sheet: Sheet = serde_json::from_str(&sheet)?;
+
+    for (i, page) in sheet.pages.iter().enumerate() {
+        let path = format!("treasure-sheet-{}.svg", i + 1);
+        fs::write(&path, page).with_context(|| format!("unable to write {}", path))?;
+        eprintln!("wrote {}", path);
+    }
+
+    for code in sheet.codes {
+        println!("{}", code.secret_key);
+    }
+
+    Ok(())
+}
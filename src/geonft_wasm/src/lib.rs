@@ -2,10 +2,15 @@ extern crate alloc;
 
 use alloc::format;
 
+mod qrcode_sheet;
 mod treasure_qrcode;
 mod utils;
+use geonft_nostd::backup;
 use geonft_nostd::crypto::{self, Keypair};
+use geonft_request::{plant_signed_bytes, TreasureDetails};
 use rand::rngs::OsRng;
+use serde::Serialize;
+use treasure_qrcode::{Ecc, QrCodeOptions};
 use wasm_bindgen::prelude::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -19,18 +24,146 @@ pub fn set_panic_hook() {
     utils::set_panic_hook();
 }
 
+/// Create a new treasure key and a QR code of its claim URL
+/// on the site whose origin is `base_url`.
 #[wasm_bindgen]
-pub fn create_qrcode() -> Option<String> {
-    let new_qrcode = treasure_qrcode::init_random_qrcode().ok();
+pub fn create_qrcode(base_url: &str) -> Option<String> {
+    let new_qrcode = treasure_qrcode::init_random_qrcode(base_url).ok();
     serde_json::to_string(&new_qrcode).ok()
 }
 
+/// Like `create_qrcode`, but rendered according to `options`,
+/// a JSON-encoded `QrCodeOptions`.
 #[wasm_bindgen]
-pub fn sanity_check_treasure_secret_url(url: &str) -> bool {
-    url.starts_with(crypto::TREASURE_SECRET_PLANT_URL_PREFIX)
-        || url.starts_with(crypto::TREASURE_SECRET_CLAIM_URL_PREFIX)
-        || url.starts_with(crypto::TREASURE_SECRET_PLANT_URL_PREFIX_LOCAL)
-        || url.starts_with(crypto::TREASURE_SECRET_CLAIM_URL_PREFIX_LOCAL)
+pub fn create_qrcode_with_options(base_url: &str, options: &str) -> Option<String> {
+    let options = serde_json::from_str(options).ok()?;
+    let new_qrcode = treasure_qrcode::init_random_qrcode_with_options(base_url, &options).ok()?;
+    serde_json::to_string(&new_qrcode).ok()
+}
+
+fn parse_qrcode_options(options: Option<String>) -> Option<QrCodeOptions> {
+    match options {
+        Some(options) => serde_json::from_str(&options).ok(),
+        None => Some(QrCodeOptions::default()),
+    }
+}
+
+/// The most QR codes that can be created in one batch.
+pub const MAX_QRCODES: usize = 10 * qrcode_sheet::CODES_PER_PAGE;
+
+/// Create `count` new treasure keys and their QR codes,
+/// returned as a JSON array.
+///
+/// `options` is an optional JSON-encoded `QrCodeOptions`.
+#[wasm_bindgen]
+pub fn create_qrcodes(count: usize, base_url: &str, options: Option<String>) -> Option<String> {
+    if count > MAX_QRCODES {
+        return None;
+    }
+    let options = parse_qrcode_options(options)?;
+    let new_qrcodes = treasure_qrcode::init_random_qrcodes(count, base_url, &options).ok()?;
+    serde_json::to_string(&new_qrcodes).ok()
+}
+
+#[derive(Serialize)]
+struct QrCodeSheet {
+    codes: Vec<treasure_qrcode::UniqueCode>,
+    /// One printable SVG document per page
+    pages: Vec<String>,
+}
+
+/// Create `count` new treasure keys laid out on printable pages,
+/// each QR code captioned with its abbreviated public key
+/// and the optional `label`.
+///
+/// `options` is an optional JSON-encoded `QrCodeOptions`,
+/// of which the sheet uses the error correction level and dark color.
+///
+/// Returns JSON with the created `codes` and the SVG `pages`.
+#[wasm_bindgen]
+pub fn create_qrcode_sheet(
+    count: usize,
+    label: Option<String>,
+    base_url: &str,
+    options: Option<String>,
+) -> Option<String> {
+    if count > MAX_QRCODES {
+        return None;
+    }
+    let options = parse_qrcode_options(options)?;
+    let codes = treasure_qrcode::init_random_qrcodes(count, base_url, &options).ok()?;
+    qrcode_sheet_json(codes, label, &options)
+}
+
+/// A new planter seed, from which `create_seeded_qrcodes`
+/// and `create_seeded_qrcode_sheet` derive treasure keys.
+#[wasm_bindgen]
+pub fn new_planter_seed() -> Option<String> {
+    crypto::new_planter_seed(&mut OsRng).ok()
+}
+
+/// Like `create_qrcodes`, but with treasure keys
+/// derived from the planter `seed`, starting at `start_index`.
+#[wasm_bindgen]
+pub fn create_seeded_qrcodes(
+    seed: &str,
+    start_index: u32,
+    count: usize,
+    base_url: &str,
+    options: Option<String>,
+) -> Option<String> {
+    if count > MAX_QRCODES {
+        return None;
+    }
+    let options = parse_qrcode_options(options)?;
+    let new_qrcodes =
+        treasure_qrcode::init_seeded_qrcodes(seed, start_index, count, base_url, &options).ok()?;
+    serde_json::to_string(&new_qrcodes).ok()
+}
+
+/// Like `create_qrcode_sheet`, but with treasure keys
+/// derived from the planter `seed`, starting at `start_index`.
+#[wasm_bindgen]
+pub fn create_seeded_qrcode_sheet(
+    seed: &str,
+    start_index: u32,
+    count: usize,
+    label: Option<String>,
+    base_url: &str,
+    options: Option<String>,
+) -> Option<String> {
+    if count > MAX_QRCODES {
+        return None;
+    }
+    let options = parse_qrcode_options(options)?;
+    let codes =
+        treasure_qrcode::init_seeded_qrcodes(seed, start_index, count, base_url, &options).ok()?;
+    qrcode_sheet_json(codes, label, &options)
+}
+
+fn qrcode_sheet_json(
+    codes: Vec<treasure_qrcode::UniqueCode>,
+    label: Option<String>,
+    options: &QrCodeOptions,
+) -> Option<String> {
+    let label = label.filter(|label| !label.is_empty());
+    let pages = qrcode_sheet::render_sheet(&codes, label.as_deref(), options).ok()?;
+    serde_json::to_string(&QrCodeSheet { codes, pages }).ok()
+}
+
+/// Whether `url` looks like a treasure secret URL
+/// from one of `allowed_origins`, a JSON array of origins
+/// like `["https://treasuretree.org"]`.
+#[wasm_bindgen]
+pub fn sanity_check_treasure_secret_url(url: &str, allowed_origins: &str) -> bool {
+    let allowed_origins: Vec<String> = match serde_json::from_str(allowed_origins) {
+        Ok(origins) => origins,
+        Err(_) => return false,
+    };
+
+    crypto::parse_treasure_secret_url(url)
+        .map(|url| url.is_allowed_origin(&allowed_origins))
+        .unwrap_or(false)
 }
 
 #[wasm_bindgen]
@@ -61,26 +194,42 @@ pub fn treasure_secret_key_to_public_key(key: &str) -> Option<String> {
 }
 
 #[wasm_bindgen]
-pub fn treasure_secret_key_to_secret_claim_url(key: &str) -> Option<String> {
+pub fn treasure_secret_key_to_secret_claim_url(key: &str, base_url: &str) -> Option<String> {
     crypto::keypair_from_treasure_secret_key(key)
         .ok()
-        .map(|kp| crypto::keypair_to_treasure_secret_claim_url(&kp).ok())
+        .map(|kp| crypto::keypair_to_treasure_secret_claim_url(&kp, base_url).ok())
         .flatten()
 }
 
+/// `details` is the JSON of the treasure details, if any.
+/// `not_before` and `not_after` are the unix timestamps
+/// bounding when the treasure can be claimed, if any.
 #[wasm_bindgen]
 pub fn sign_plant_with_treasure_secret_key(
     treasure_secret_key: &str,
     account_public_key: &str,
     treasure_hash: &str,
+    details: Option<String>,
+    not_before: Option<u32>,
+    not_after: Option<u32>,
 ) -> Option<String> {
     let treasure_secret_key = crypto::decode_treasure_secret_key(treasure_secret_key).ok()?;
     let account_public_key = crypto::decode_account_public_key(account_public_key).ok()?;
+    let details: Option<TreasureDetails> = match details {
+        Some(details) => Some(serde_json::from_str(&details).ok()?),
+        None => None,
+    };
+    let details = plant_signed_bytes(
+        &details,
+        not_before.map(i64::from),
+        not_after.map(i64::from),
+    );
 
     let signature = crypto::sign_plant_request_for_treasure(
         &treasure_secret_key,
         &account_public_key,
         treasure_hash.as_bytes(),
+        &details,
     )
     .ok()?;
 
@@ -115,16 +264,125 @@ pub fn sign_claim_with_treasure_secret_key(
     crypto::encode_signature(&signature).ok()
 }
 
+/// `timestamp` is seconds since the unix epoch.
 #[wasm_bindgen]
 pub fn sign_claim_with_account_secret_key(
     account_secret_key: &str,
     treasure_public_key: &str,
+    timestamp: u32,
+) -> Option<String> {
+    let account_secret_key = crypto::decode_account_secret_key(account_secret_key).ok()?;
+    let treasure_public_key = crypto::decode_treasure_public_key(treasure_public_key).ok()?;
+
+    let signature = crypto::sign_claim_request_for_account(
+        &account_secret_key,
+        &treasure_public_key,
+        timestamp as u64,
+    )
+    .ok()?;
+
+    crypto::encode_signature(&signature).ok()
+}
+
+/// `image_hash` is from `get_hash` of the base64 log image, if any.
+#[wasm_bindgen]
+pub fn sign_log_with_account_secret_key(
+    account_secret_key: &str,
+    treasure_public_key: &str,
+    comment: &str,
+    image_hash: Option<String>,
 ) -> Option<String> {
     let account_secret_key = crypto::decode_account_secret_key(account_secret_key).ok()?;
     let treasure_public_key = crypto::decode_treasure_public_key(treasure_public_key).ok()?;
+    let image_hash = image_hash.unwrap_or_default();
+
+    let signature = crypto::sign_log_request_for_account(
+        &account_secret_key,
+        &treasure_public_key,
+        comment,
+        image_hash.as_bytes(),
+    )
+    .ok()?;
+
+    crypto::encode_signature(&signature).ok()
+}
+
+/// `image_hash` is from `get_hash` of the base64 log image, if any.
+#[wasm_bindgen]
+pub fn sign_log_with_treasure_secret_key(
+    treasure_secret_key: &str,
+    account_public_key: &str,
+    comment: &str,
+    image_hash: Option<String>,
+) -> Option<String> {
+    let treasure_secret_key = crypto::decode_treasure_secret_key(treasure_secret_key).ok()?;
+    let account_public_key = crypto::decode_account_public_key(account_public_key).ok()?;
+    let image_hash = image_hash.unwrap_or_default();
+
+    let signature = crypto::sign_log_request_for_treasure(
+        &treasure_secret_key,
+        &account_public_key,
+        comment,
+        image_hash.as_bytes(),
+    )
+    .ok()?;
+
+    crypto::encode_signature(&signature).ok()
+}
+
+#[wasm_bindgen]
+pub fn sign_retire_with_account_secret_key(
+    account_secret_key: &str,
+    treasure_public_key: &str,
+) -> Option<String> {
+    let account_secret_key = crypto::decode_account_secret_key(account_secret_key).ok()?;
+    let treasure_public_key = crypto::decode_treasure_public_key(treasure_public_key).ok()?;
+
+    let signature =
+        crypto::sign_retire_request_for_account(&account_secret_key, &treasure_public_key).ok()?;
+
+    crypto::encode_signature(&signature).ok()
+}
+
+/// Sign the Solana wallet a claimed treasure's NFT is minted to.
+#[wasm_bindgen]
+pub fn sign_claim_wallet_with_account_secret_key(
+    account_secret_key: &str,
+    treasure_public_key: &str,
+    wallet: &str,
+) -> Option<String> {
+    let account_secret_key = crypto::decode_account_secret_key(account_secret_key).ok()?;
+    let treasure_public_key = crypto::decode_treasure_public_key(treasure_public_key).ok()?;
+    let wallet = crypto::decode_wallet_address(wallet).ok()?;
+
+    let signature =
+        crypto::sign_claim_wallet_for_account(&account_secret_key, &treasure_public_key, &wallet)
+            .ok()?;
+
+    crypto::encode_signature(&signature).ok()
+}
+
+/// The message a Solana wallet signs to link itself to an account.
+///
+/// `timestamp` is seconds since the unix epoch.
+#[wasm_bindgen]
+pub fn link_wallet_message(account_public_key: &str, timestamp: u32) -> String {
+    crypto::link_wallet_message(account_public_key, timestamp as u64)
+}
+
+/// Sign the Solana wallet linked to an account.
+#[wasm_bindgen]
+pub fn sign_link_wallet_with_account_secret_key(
+    account_secret_key: &str,
+    wallet: &str,
+    timestamp: u32,
+) -> Option<String> {
+    let account_secret_key = crypto::decode_account_secret_key(account_secret_key).ok()?;
+    let wallet = crypto::decode_wallet_address(wallet).ok()?;
 
     let signature =
-        crypto::sign_claim_request_for_account(&account_secret_key, &treasure_public_key).ok()?;
+        crypto::sign_link_wallet_for_account(&account_secret_key, &wallet, timestamp as u64)
+            .ok()?;
 
     crypto::encode_signature(&signature).ok()
 }
@@ -135,6 +393,19 @@ pub fn new_account_secret_key() -> Option<String> {
     crypto::encode_account_secret_key(&keypair.secret).ok()
 }
 
+/// A new 12 word recovery phrase for an account.
+#[wasm_bindgen]
+pub fn new_account_mnemonic() -> Option<String> {
+    crypto::new_account_mnemonic(&mut OsRng).ok()
+}
+
+/// The account secret key derived from a recovery phrase.
+#[wasm_bindgen]
+pub fn account_mnemonic_to_secret_key(phrase: &str) -> Option<String> {
+    let keypair = crypto::keypair_from_account_mnemonic(phrase).ok()?;
+    crypto::encode_account_secret_key(&keypair.secret).ok()
+}
+
 #[wasm_bindgen]
 pub fn account_secret_key_to_public_key(key: &str) -> Option<String> {
     crypto::keypair_from_account_secret_key(key)
@@ -144,6 +415,35 @@ pub fn account_secret_key_to_public_key(key: &str) -> Option<String> {
         .flatten()
 }
 
+/// Encrypt an account secret key with `passphrase`,
+/// returning a `gae1...` backup string.
+#[wasm_bindgen]
+pub fn encrypt_account_secret_key(key: &str, passphrase: &str) -> Option<String> {
+    let key = crypto::decode_account_secret_key(key).ok()?;
+    backup::encrypt_account_secret_key(&key, passphrase, &mut OsRng).ok()
+}
+
+/// Decrypt a backup from `encrypt_account_secret_key`,
+/// returning the account secret key.
+#[wasm_bindgen]
+pub fn decrypt_account_secret_key(backup: &str, passphrase: &str) -> Option<String> {
+    let key = backup::decrypt_account_secret_key(backup, passphrase).ok()?;
+    crypto::encode_account_secret_key(&key).ok()
+}
+
+/// An SVG QR code of an account backup, for printing.
+#[wasm_bindgen]
+pub fn account_backup_to_qrcode(backup: &str) -> Option<String> {
+    let options = QrCodeOptions {
+        ecc: Ecc::Medium,
+        border: 4,
+        ..QrCodeOptions::default()
+    };
+    // Uppercase fits the QR alphanumeric mode
+    let qrcode = treasure_qrcode::encode_text(&backup.to_uppercase(), &options).ok()?;
+    treasure_qrcode::render_svg(&qrcode, &options).ok()
+}
+
 fn new_keypair() -> Keypair {
     crypto::generate_keypair(&mut OsRng)
 }
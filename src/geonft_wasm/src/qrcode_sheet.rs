@@ -0,0 +1,227 @@
+//! Printable sheets of treasure QR codes
+//!
+//! Each page is an A4 SVG document laid out as a grid of cells,
+//! separated by dashed cut lines.
+//! Every cell contains one QR code,
+//! the abbreviated treasure public key and derivation index,
+//! and an optional label.
+
+use anyhow::Result;
+
+use crate::treasure_qrcode::{self, escape_xml, QrCodeOptions, UniqueCode};
+
+const PAGE_WIDTH_MM: f64 = 210.0;
+const PAGE_HEIGHT_MM: f64 = 297.0;
+const COLUMNS: usize = 3;
+const ROWS: usize = 4;
+const QR_SIZE_MM: f64 = 50.0;
+const TEXT_SIZE_MM: f64 = 3.5;
+
+pub const CODES_PER_PAGE: usize = COLUMNS * ROWS;
+
+/// Render `codes` onto as many pages as needed,
+/// returning one SVG document per page.
+///
+/// The codes are drawn as by `treasure_qrcode::render_svg`,
+/// with the colors, quiet zone and logo from `options`.
+pub fn render_sheet(
+    codes: &[UniqueCode],
+    label: Option<&str>,
+    options: &QrCodeOptions,
+) -> Result<Vec<String>> {
+    codes
+        .chunks(CODES_PER_PAGE)
+        .map(|page_codes| render_page(page_codes, label, options))
+        .collect()
+}
+
+fn render_page(
+    codes: &[UniqueCode],
+    label: Option<&str>,
+    options: &QrCodeOptions,
+) -> Result<String> {
+    let cell_width = PAGE_WIDTH_MM / COLUMNS as f64;
+    let cell_height = PAGE_HEIGHT_MM / ROWS as f64;
+
+    let mut svg = String::new();
+
+    svg += &format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" \
+         width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n",
+        w = PAGE_WIDTH_MM,
+        h = PAGE_HEIGHT_MM,
+    );
+    svg += &format!(
+        "<rect width=\"{}\" height=\"{}\" fill=\"#FFFFFF\"/>\n",
+        PAGE_WIDTH_MM, PAGE_HEIGHT_MM
+    );
+
+    // Cut lines between columns and rows
+    svg += "<g stroke=\"#888888\" stroke-width=\"0.2\" stroke-dasharray=\"2,2\">\n";
+    for column in 1..COLUMNS {
+        let x = cell_width * column as f64;
+        svg += &format!(
+            "<line x1=\"{x}\" y1=\"0\" x2=\"{x}\" y2=\"{h}\"/>\n",
+            x = x,
+            h = PAGE_HEIGHT_MM
+        );
+    }
+    for row in 1..ROWS {
+        let y = cell_height * row as f64;
+        svg += &format!(
+            "<line x1=\"0\" y1=\"{y}\" x2=\"{w}\" y2=\"{y}\"/>\n",
+            y = y,
+            w = PAGE_WIDTH_MM
+        );
+    }
+    svg += "</g>\n";
+
+    for (i, code) in codes.iter().enumerate() {
+        let cell_x = cell_width * (i % COLUMNS) as f64;
+        let cell_y = cell_height * (i / COLUMNS) as f64;
+        svg += &render_cell(code, label, options, cell_x, cell_y, cell_width)?;
+    }
+
+    svg += "</svg>\n";
+
+    Ok(svg)
+}
+
+fn render_cell(
+    code: &UniqueCode,
+    label: Option<&str>,
+    options: &QrCodeOptions,
+    cell_x: f64,
+    cell_y: f64,
+    cell_width: f64,
+) -> Result<String> {
+    let qrcode = treasure_qrcode::encode_text(&code.url, options)?;
+
+    let qr_x = cell_x + (cell_width - QR_SIZE_MM) / 2.0;
+    let qr_y = cell_y + 8.0;
+    let text_x = cell_x + cell_width / 2.0;
+
+    let mut svg = String::new();
+
+    // The code and its quiet zone scaled into a square
+    svg += &format!(
+        "<svg x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" \
+         viewBox=\"0 0 {d} {d}\" stroke=\"none\">\n",
+        qr_x,
+        qr_y,
+        s = QR_SIZE_MM,
+        d = treasure_qrcode::svg_dimension(&qrcode, options)
+    );
+    svg += &treasure_qrcode::render_svg_contents(&qrcode, options);
+    svg += "</svg>\n";
+
+    let mut abbrev = geonft_nostd::abbrev_pubkey(&code.public_key);
+    if let Some(index) = code.index {
+        abbrev += &format!(" #{}", index);
+    }
+    svg += &format!(
+        "<text x=\"{}\" y=\"{}\" font-family=\"monospace\" font-size=\"{}\" text-anchor=\"middle\">{}</text>\n",
+        text_x,
+        qr_y + QR_SIZE_MM + 6.0,
+        TEXT_SIZE_MM,
+        escape_xml(&abbrev)
+    );
+
+    if let Some(label) = label {
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"monospace\" font-size=\"{}\" text-anchor=\"middle\">{}</text>\n",
+            text_x,
+            qr_y + QR_SIZE_MM + 12.0,
+            TEXT_SIZE_MM,
+            escape_xml(label)
+        );
+    }
+
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: &str = "gps1yg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3q5cup3r";
+
+    fn codes(count: usize) -> Vec<UniqueCode> {
+        treasure_qrcode::init_seeded_qrcodes(
+            SEED,
+            0,
+            count,
+            "https://treasuretree.org",
+            &QrCodeOptions::default(),
+        )
+        .unwrap()
+    }
+
+    /// The number of QR codes drawn on a page
+    fn cells(page: &str) -> usize {
+        page.matches("<path ").count()
+    }
+
+    #[test]
+    fn page_counts() {
+        let options = QrCodeOptions::default();
+        let counts = [(0, 0), (1, 1), (CODES_PER_PAGE, 1), (CODES_PER_PAGE + 1, 2)];
+
+        for (code_count, page_count) in counts.iter() {
+            let pages = render_sheet(&codes(*code_count), None, &options).unwrap();
+            assert_eq!(pages.len(), *page_count, "{} codes", code_count);
+        }
+    }
+
+    #[test]
+    fn cell_counts() {
+        let options = QrCodeOptions::default();
+        let pages = render_sheet(&codes(CODES_PER_PAGE + 1), Some("Park"), &options).unwrap();
+
+        assert_eq!(cells(&pages[0]), CODES_PER_PAGE);
+        assert_eq!(cells(&pages[1]), 1);
+
+        // The key and the label under each code
+        assert_eq!(pages[0].matches("<text ").count(), CODES_PER_PAGE * 2);
+        assert_eq!(pages[1].matches("<text ").count(), 2);
+        assert!(pages[1].contains(&format!(" #{}<", CODES_PER_PAGE)));
+
+        // Cut lines between the cells, even on a part-filled page
+        assert_eq!(pages[1].matches("<line ").count(), COLUMNS - 1 + ROWS - 1);
+    }
+
+    #[test]
+    fn cell_options() {
+        let options: QrCodeOptions = serde_json::from_str(
+            r##"{ "border": 4, "dark_color": "#102030", "light_color": "#F0E0D0",
+                  "logo": "data:image/png;base64,AA==" }"##,
+        )
+        .unwrap();
+        let pages = render_sheet(&codes(2), None, &options).unwrap();
+
+        let qrcode = treasure_qrcode::encode_text(&codes(1)[0].url, &options).unwrap();
+        let dimension = qrcode.size() + 8;
+        assert_eq!(
+            pages[0]
+                .matches(&format!("viewBox=\"0 0 {d} {d}\"", d = dimension))
+                .count(),
+            2
+        );
+        assert_eq!(pages[0].matches("fill=\"#102030\"").count(), 2);
+        // The light color behind each code and its logo
+        assert_eq!(pages[0].matches("fill=\"#F0E0D0\"").count(), 4);
+        assert_eq!(
+            pages[0]
+                .matches("<image href=\"data:image/png;base64,AA==\"")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn label_escaped() {
+        let options = QrCodeOptions::default();
+        let pages = render_sheet(&codes(1), Some("Fish & <Chips>"), &options).unwrap();
+        assert!(pages[0].contains(">Fish &amp; &lt;Chips&gt;</text>"));
+    }
+}
@@ -1,5 +1,5 @@
-use anyhow::Result;
-use geonft_nostd::crypto;
+use anyhow::{anyhow, bail, Result};
+use geonft_nostd::crypto::{self, Keypair};
 use qrcodegen::QrCode;
 use qrcodegen::QrCodeEcc;
 use serde::{Deserialize, Serialize};
@@ -7,20 +7,455 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub struct UniqueCode {
     pub secret_key: String,
+    pub public_key: String,
+    /// Markup for the QR code, either an SVG document or an `img` tag
     pub qrcode: String,
     pub url: String,
+    /// The derivation index, if derived from a planter seed
+    pub index: Option<u32>,
 }
 
-pub fn init_random_qrcode() -> Result<UniqueCode> {
+/// How to render a treasure QR code
+///
+/// Deserialized from JSON like
+/// `{ "ecc": "quartile", "border": 4, "format": "png" }`,
+/// with any missing fields taking their default.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct QrCodeOptions {
+    /// Error correction level.
+    /// Higher levels survive more dirt and damage,
+    /// at the cost of a denser code.
+    pub ecc: Ecc,
+    /// Width of the quiet zone around the code, in modules
+    pub border: i32,
+    /// Color of dark modules, as `#RRGGBB`
+    pub dark_color: String,
+    /// Color of light modules and the quiet zone, as `#RRGGBB`
+    pub light_color: String,
+    /// An image to draw over the center of the code,
+    /// as a `data:image/...` URI so the code has no external links.
+    /// Forces the highest error correction level.
+    /// Only supported for SVG.
+    pub logo: Option<String>,
+    pub format: Format,
+    /// Pixels per module when rendering PNG
+    pub png_scale: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Ecc {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Svg,
+    Png,
+}
+
+impl Default for QrCodeOptions {
+    fn default() -> QrCodeOptions {
+        QrCodeOptions {
+            ecc: Ecc::Low,
+            border: 0,
+            dark_color: "#000000".to_string(),
+            light_color: "#FFFFFF".to_string(),
+            logo: None,
+            format: Format::Svg,
+            png_scale: 8,
+        }
+    }
+}
+
+/// The fraction of the code's width covered by a logo.
+///
+/// This covers about 6% of the modules,
+/// well within what the highest error correction level can recover.
+const LOGO_FRACTION: f64 = 0.25;
+
+const MAX_BORDER: i32 = 16;
+const MAX_PNG_SCALE: u32 = 32;
+
+impl QrCodeOptions {
+    /// The error correction level to encode with,
+    /// bumped up if part of the code is hidden by a logo.
+    pub fn effective_ecc(&self) -> QrCodeEcc {
+        if self.logo.is_some() {
+            return QrCodeEcc::High;
+        }
+
+        match self.ecc {
+            Ecc::Low => QrCodeEcc::Low,
+            Ecc::Medium => QrCodeEcc::Medium,
+            Ecc::Quartile => QrCodeEcc::Quartile,
+            Ecc::High => QrCodeEcc::High,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.border < 0 || self.border > MAX_BORDER {
+            bail!("QR code border must be between 0 and {}", MAX_BORDER);
+        }
+        if self.png_scale < 1 || self.png_scale > MAX_PNG_SCALE {
+            bail!("PNG scale must be between 1 and {}", MAX_PNG_SCALE);
+        }
+        parse_color(&self.dark_color)?;
+        parse_color(&self.light_color)?;
+        if let Some(logo) = &self.logo {
+            if let Format::Png = self.format {
+                bail!("QR code logos are only supported for SVG");
+            }
+            if !logo.starts_with("data:image/") {
+                bail!("QR code logo must be a data:image URI");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create a new treasure key and a QR code of its compact claim URL
+/// on the site whose origin is `base_url`.
+pub fn init_random_qrcode(base_url: &str) -> Result<UniqueCode> {
+    init_random_qrcode_with_options(base_url, &QrCodeOptions::default())
+}
+
+pub fn init_random_qrcode_with_options(
+    base_url: &str,
+    options: &QrCodeOptions,
+) -> Result<UniqueCode> {
+    options.validate()?;
+
     let keypair = super::new_keypair();
+    qrcode_for_keypair(&keypair, None, base_url, options)
+}
+
+pub fn init_random_qrcodes(
+    count: usize,
+    base_url: &str,
+    options: &QrCodeOptions,
+) -> Result<Vec<UniqueCode>> {
+    (0..count)
+        .map(|_| init_random_qrcode_with_options(base_url, options))
+        .collect()
+}
+
+/// Derive `count` treasure keys from a planter seed,
+/// starting at `start_index`, and create their QR codes.
+///
+/// The same seed and indexes always create the same codes,
+/// so a sheet can be re-created from the seed alone.
+pub fn init_seeded_qrcodes(
+    seed: &str,
+    start_index: u32,
+    count: usize,
+    base_url: &str,
+    options: &QrCodeOptions,
+) -> Result<Vec<UniqueCode>> {
+    options.validate()?;
+
+    let seed = crypto::decode_planter_seed(seed)?;
+
+    (0..count as u32)
+        .map(|i| {
+            let index = start_index
+                .checked_add(i)
+                .ok_or_else(|| anyhow!("treasure key index overflow"))?;
+            let keypair = crypto::derive_treasure_keypair(&seed, index)?;
+            qrcode_for_keypair(&keypair, Some(index), base_url, options)
+        })
+        .collect()
+}
+
+fn qrcode_for_keypair(
+    keypair: &Keypair,
+    index: Option<u32>,
+    base_url: &str,
+    options: &QrCodeOptions,
+) -> Result<UniqueCode> {
     let secret_key_string = crypto::encode_treasure_secret_key(&keypair.secret)?;
-    let url = crypto::keypair_to_treasure_secret_claim_url(&keypair)?;
-    let qrcode = QrCode::encode_text(&url, QrCodeEcc::Low).unwrap();
+    let public_key_string = crypto::encode_treasure_public_key(&keypair.public)?;
+    let url = crypto::keypair_to_treasure_secret_compact_claim_url(keypair, base_url)?;
+    let qrcode = encode_text(&url, options)?;
+
+    let qrcode = match options.format {
+        Format::Svg => render_svg(&qrcode, options)?,
+        Format::Png => format!(
+            "<img src=\"data:image/png;base64,{}\" alt=\"treasure QR code\">",
+            base64::encode(render_png(&qrcode, options)?)
+        ),
+    };
 
     Ok(UniqueCode {
         secret_key: secret_key_string,
-        // Argument is the size, bigger number means smaller size on the page
-        qrcode: qrcode.to_svg_string(0),
+        public_key: public_key_string,
+        qrcode,
         url,
+        index,
     })
 }
+
+pub fn encode_text(text: &str, options: &QrCodeOptions) -> Result<QrCode> {
+    QrCode::encode_text(text, options.effective_ecc())
+        .map_err(|_| anyhow!("text too long for QR code"))
+}
+
+/// Render a standalone SVG document,
+/// sized by its viewbox so it scales to its container.
+pub fn render_svg(qrcode: &QrCode, options: &QrCodeOptions) -> Result<String> {
+    let mut svg = String::new();
+
+    svg += &format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" \
+         viewBox=\"0 0 {d} {d}\" stroke=\"none\">\n",
+        d = svg_dimension(qrcode, options)
+    );
+    svg += &render_svg_contents(qrcode, options);
+    svg += "</svg>\n";
+
+    Ok(svg)
+}
+
+/// The width of the code and its quiet zone, in modules
+pub fn svg_dimension(qrcode: &QrCode, options: &QrCodeOptions) -> i32 {
+    qrcode.size() + options.border * 2
+}
+
+/// The elements of an SVG QR code,
+/// drawn in a viewbox `svg_dimension` modules wide.
+pub fn render_svg_contents(qrcode: &QrCode, options: &QrCodeOptions) -> String {
+    let border = options.border;
+    let dimension = svg_dimension(qrcode, options);
+    let dark_color = escape_xml(&options.dark_color);
+    let light_color = escape_xml(&options.light_color);
+
+    let mut svg = String::new();
+
+    svg += &format!(
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+        light_color
+    );
+    svg += &format!(
+        "<path transform=\"translate({b} {b})\" d=\"{}\" fill=\"{}\"/>\n",
+        qrcode_path(qrcode),
+        dark_color,
+        b = border
+    );
+
+    if let Some(logo) = &options.logo {
+        let logo_size = qrcode.size() as f64 * LOGO_FRACTION;
+        let logo_offset = (dimension as f64 - logo_size) / 2.0;
+        svg += &format!(
+            "<rect x=\"{o}\" y=\"{o}\" width=\"{s}\" height=\"{s}\" fill=\"{}\"/>\n",
+            light_color,
+            o = logo_offset,
+            s = logo_size
+        );
+        svg += &format!(
+            "<image href=\"{}\" x=\"{o}\" y=\"{o}\" width=\"{s}\" height=\"{s}\"/>\n",
+            escape_xml(logo),
+            o = logo_offset,
+            s = logo_size
+        );
+    }
+
+    svg
+}
+
+/// Rasterize to an RGB PNG with `png_scale` pixels per module.
+pub fn render_png(qrcode: &QrCode, options: &QrCodeOptions) -> Result<Vec<u8>> {
+    let dark = parse_color(&options.dark_color)?;
+    let light = parse_color(&options.light_color)?;
+    let border = options.border;
+    let scale = options.png_scale;
+    let dimension = (qrcode.size() + border * 2) as u32;
+    let pixels = dimension * scale;
+
+    let mut data = Vec::with_capacity((pixels * pixels * 3) as usize);
+    for py in 0..pixels {
+        for px in 0..pixels {
+            let x = (px / scale) as i32 - border;
+            let y = (py / scale) as i32 - border;
+            // get_module is false outside the code, i.e. the quiet zone
+            let color = if qrcode.get_module(x, y) { dark } else { light };
+            data.extend_from_slice(&color);
+        }
+    }
+
+    let mut png_bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_bytes, pixels, pixels);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| anyhow!("{}", e))?;
+        writer
+            .write_image_data(&data)
+            .map_err(|e| anyhow!("{}", e))?;
+    }
+
+    Ok(png_bytes)
+}
+
+/// An SVG path drawing each dark module as a unit square.
+pub fn qrcode_path(qrcode: &QrCode) -> String {
+    let mut path = String::new();
+    for y in 0..qrcode.size() {
+        for x in 0..qrcode.size() {
+            if qrcode.get_module(x, y) {
+                path += &format!("M{},{}h1v1h-1z", x, y);
+            }
+        }
+    }
+    path
+}
+
+/// Parse a `#RRGGBB` color
+fn parse_color(color: &str) -> Result<[u8; 3]> {
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .ok_or_else(|| anyhow!("color must be #RRGGBB: {}", color))?;
+
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow!("invalid color: {}", color))
+    };
+
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_options(json: &str) -> QrCodeOptions {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn options_default() {
+        let options = parse_options("{}");
+        assert!(matches!(options.ecc, Ecc::Low));
+        assert_eq!(options.border, 0);
+        assert_eq!(options.dark_color, "#000000");
+        assert_eq!(options.light_color, "#FFFFFF");
+        assert!(options.logo.is_none());
+        assert!(matches!(options.format, Format::Svg));
+        assert_eq!(options.png_scale, 8);
+        options.validate().unwrap();
+    }
+
+    #[test]
+    fn options_parse() {
+        let options = parse_options(
+            r##"{ "ecc": "quartile", "border": 4, "format": "png",
+                  "dark_color": "#1a2B3c", "png_scale": 2 }"##,
+        );
+        assert!(matches!(options.ecc, Ecc::Quartile));
+        assert!(options.effective_ecc() == QrCodeEcc::Quartile);
+        assert_eq!(options.border, 4);
+        assert_eq!(options.dark_color, "#1a2B3c");
+        assert_eq!(options.light_color, "#FFFFFF");
+        assert!(matches!(options.format, Format::Png));
+        assert_eq!(options.png_scale, 2);
+        options.validate().unwrap();
+
+        assert!(serde_json::from_str::<QrCodeOptions>(r#"{ "ecc": "extreme" }"#).is_err());
+        assert!(serde_json::from_str::<QrCodeOptions>(r#"{ "format": "gif" }"#).is_err());
+    }
+
+    #[test]
+    fn options_logo_forces_high_ecc() {
+        let options = parse_options(r#"{ "ecc": "low", "logo": "data:image/png;base64,AA==" }"#);
+        assert!(options.effective_ecc() == QrCodeEcc::High);
+        options.validate().unwrap();
+
+        let qrcode = encode_text("HTTPS://TREASURETREE.ORG/C/GTS1", &options).unwrap();
+        let svg = render_svg(&qrcode, &options).unwrap();
+        assert!(svg.contains("<image href=\"data:image/png;base64,AA==\""));
+
+        let options = parse_options(r#"{ "format": "png", "logo": "data:image/png;base64,AA==" }"#);
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn options_logo_embedded() {
+        for logo in &[
+            "/images/logo.svg",
+            "https://example.com/logo.png",
+            "data:text/html,hi",
+        ] {
+            let options = QrCodeOptions {
+                logo: Some(logo.to_string()),
+                ..QrCodeOptions::default()
+            };
+            assert!(options.validate().is_err(), "{}", logo);
+        }
+    }
+
+    #[test]
+    fn options_out_of_range() {
+        for json in &[
+            r#"{ "border": -1 }"#,
+            r#"{ "border": 17 }"#,
+            r#"{ "png_scale": 0 }"#,
+            r#"{ "png_scale": 33 }"#,
+        ] {
+            assert!(parse_options(json).validate().is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn invalid_colors() {
+        assert_eq!(parse_color("#00ff80").unwrap(), [0x00, 0xff, 0x80]);
+
+        for color in &[
+            "", "#", "000000", "#00000", "#0000000", "#00gg00", "#éé00", "red",
+        ] {
+            assert!(parse_color(color).is_err(), "{}", color);
+
+            let options = QrCodeOptions {
+                dark_color: color.to_string(),
+                ..QrCodeOptions::default()
+            };
+            assert!(options.validate().is_err(), "{}", color);
+
+            let options = QrCodeOptions {
+                light_color: color.to_string(),
+                ..QrCodeOptions::default()
+            };
+            assert!(options.validate().is_err(), "{}", color);
+        }
+    }
+
+    #[test]
+    fn render_png_size() {
+        let options = parse_options(r#"{ "format": "png", "border": 2, "png_scale": 3 }"#);
+        let qrcode = encode_text("HTTPS://TREASURETREE.ORG/C/GTS1", &options).unwrap();
+        let png_bytes = render_png(&qrcode, &options).unwrap();
+
+        let decoder = png::Decoder::new(&png_bytes[..]);
+        let (info, _) = decoder.read_info().unwrap();
+        let pixels = ((qrcode.size() + 2 * 2) * 3) as u32;
+        assert_eq!((info.width, info.height), (pixels, pixels));
+    }
+}
@@ -3,10 +3,19 @@
 //! These are all mounted under `/api/` and only used from JS.
 
 use crate::errors::{GeonftError, Result};
+use crate::events::EventBus;
+use crate::images;
+use crate::leaderboard::{self, Leaderboard, Rankings, Window};
+use ed25519_dalek::Verifier;
 use geonft_nostd::crypto;
-use geonft_request::{ClaimRequest, PlantRequest};
-use geonft_shared::io;
+use geonft_request::{
+    plant_signed_bytes, ClaimRequest, LinkWalletRequest, LogRequest, PlantRequest, RetireRequest,
+    TreasureDetails,
+};
+use geonft_shared::io::{self, TreasureTemplateData};
 use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::State;
+use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::BufWriter;
 use std::path::Path;
@@ -20,6 +29,8 @@ pub struct PlantResponse;
 ///
 /// - The treasure has not already been planted.
 /// - The image can be decoded and has an acceptable type.
+/// - The details, if any, are within limits.
+/// - The claim window, if any, is not empty and hasn't closed.
 /// - The account key is valid.
 /// - The treasure key is valid.
 /// - The account signature.
@@ -30,7 +41,11 @@ pub struct PlantResponse;
 /// with the encoded pubkey as the name of the file.
 /// The pubkey can be used later to retrieve (or claim) the treasure.
 #[post("/api/plant", format = "json", data = "<plant_info>")]
-pub fn plant_treasure_with_key(plant_info: Json<PlantRequest>) -> Result<Json<PlantResponse>> {
+pub fn plant_treasure_with_key(
+    plant_info: Json<PlantRequest>,
+    leaderboard: &State<Leaderboard>,
+    events: &State<EventBus>,
+) -> Result<Json<PlantResponse>> {
     let treasure_key_decode = crypto::decode_treasure_public_key(&plant_info.treasure_public_key)?;
     let treasure_key_encode = crypto::encode_treasure_public_key(&treasure_key_decode)?;
 
@@ -46,10 +61,21 @@ pub fn plant_treasure_with_key(plant_info: Json<PlantRequest>) -> Result<Json<Pl
 
     let treasure_hash = crypto::get_hash(&plant_info.image)?;
 
+    if let Some(details) = &plant_info.details {
+        check_treasure_details(details)?;
+    }
+    check_claim_window(plant_info.not_before, plant_info.not_after)?;
+    let details = plant_signed_bytes(
+        &plant_info.details,
+        plant_info.not_before,
+        plant_info.not_after,
+    );
+
     crypto::verify_plant_request_for_treasure(
         &treasure_key_decode,
         &account_key_decode,
         treasure_hash.as_bytes(),
+        &details,
         &treasure_signature,
     )?;
 
@@ -66,9 +92,90 @@ pub fn plant_treasure_with_key(plant_info: Json<PlantRequest>) -> Result<Json<Pl
     let file = BufWriter::new(file);
     serde_json::to_writer(file, &plant_info.0)?;
 
+    let account_key_encode = crypto::encode_account_public_key(&account_key_decode)?;
+    io::record_account_plant(&account_key_encode, &treasure_key_encode)?;
+    leaderboard.record_plant(&account_key_encode);
+    events.publish_plant(&treasure_key_encode, &account_key_encode);
+
     Ok(Json(PlantResponse))
 }
 
+pub const MAX_TITLE_LEN: usize = 80;
+pub const MAX_HINT_LEN: usize = 1000;
+/// Difficulty and terrain ratings are 1 to 5
+pub const MAX_RATING: u8 = 5;
+
+fn check_treasure_details(details: &TreasureDetails) -> Result<()> {
+    if details.title.chars().count() > MAX_TITLE_LEN {
+        return Err(GeonftError::DecodeError(format!(
+            "treasure title is too long"
+        )));
+    }
+    if details.hint.chars().count() > MAX_HINT_LEN {
+        return Err(GeonftError::DecodeError(format!(
+            "treasure hint is too long"
+        )));
+    }
+    if !(1..=MAX_RATING).contains(&details.difficulty) {
+        return Err(GeonftError::DecodeError(format!(
+            "treasure difficulty must be 1 to 5"
+        )));
+    }
+    if !(1..=MAX_RATING).contains(&details.terrain) {
+        return Err(GeonftError::DecodeError(format!(
+            "treasure terrain must be 1 to 5"
+        )));
+    }
+    if let Some(expires) = &details.expires {
+        if chrono::DateTime::parse_from_rfc3339(expires).is_err() {
+            return Err(GeonftError::DecodeError(format!(
+                "treasure expiry must be an RFC 3339 time"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_claim_window(not_before: Option<i64>, not_after: Option<i64>) -> Result<()> {
+    if let (Some(not_before), Some(not_after)) = (not_before, not_after) {
+        if not_before >= not_after {
+            return Err(GeonftError::DecodeError(format!(
+                "treasure claim window must start before it ends"
+            )));
+        }
+    }
+    if let Some(not_after) = not_after {
+        if not_after <= chrono::Utc::now().timestamp() {
+            return Err(GeonftError::DecodeError(format!(
+                "treasure claim window has already closed"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// How far a signed timestamp may be from the server's clock, in seconds
+const MAX_CLOCK_SKEW: u64 = 10 * 60;
+
+fn check_clock_skew(timestamp: u64, what: &str) -> Result<()> {
+    let now = chrono::Utc::now().timestamp() as u64;
+    let skew = if now > timestamp {
+        now - timestamp
+    } else {
+        timestamp - now
+    };
+    if skew > MAX_CLOCK_SKEW {
+        return Err(GeonftError::DecodeError(format!(
+            "{} timestamp is too far from the current time",
+            what
+        )));
+    }
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ClaimResponse;
 
@@ -79,13 +186,25 @@ pub struct ClaimResponse;
 /// - The account key is valid.
 /// - The treasure key is valid.
 /// - The treasure exists,
+/// - The treasure isn't retired.
+/// - The treasure hasn't already been claimed.
+/// - The timestamp is close to the current time.
+/// - The treasure's claim window, if any, is open,
+///   both now and at the timestamp.
 /// - The account signature.
 /// - The treasure signature.
+/// - The wallet signature, if a wallet to mint the NFT to is given.
 ///
 /// If the checks pass then store a record indicating
 /// the treasure was claimed by the logged in user.
+/// If the claim doesn't name a wallet,
+/// the wallet currently linked to the account is recorded instead.
 #[post("/api/claim", format = "json", data = "<claim_info>")]
-pub fn claim_treasure_with_key(claim_info: Json<ClaimRequest>) -> Result<Json<ClaimResponse>> {
+pub fn claim_treasure_with_key(
+    claim_info: Json<ClaimRequest>,
+    leaderboard: &State<Leaderboard>,
+    events: &State<EventBus>,
+) -> Result<Json<ClaimResponse>> {
     let treasure_key_decode = crypto::decode_treasure_public_key(&claim_info.treasure_public_key)?;
     let treasure_key_encode = crypto::encode_treasure_public_key(&treasure_key_decode)?;
 
@@ -94,6 +213,40 @@ pub fn claim_treasure_with_key(claim_info: Json<ClaimRequest>) -> Result<Json<Cl
         return Err(GeonftError::FileError(format!("Treasure doesn't exist")));
     }
 
+    if io::is_retired(&treasure_key_encode) {
+        return Err(GeonftError::FileError(format!("Treasure is retired")));
+    }
+
+    let filename = format!("{}/{}", io::CLAIM_DIR, treasure_key_encode);
+    if Path::new(&filename).is_file() {
+        return Err(GeonftError::FileError(format!("Treasure already claimed")));
+    }
+
+    let timestamp = claim_info
+        .timestamp
+        .ok_or_else(|| GeonftError::DecodeError(format!("claim timestamp is required")))?;
+    check_clock_skew(timestamp, "claim")?;
+
+    // Solana checks the window at the signed claim time,
+    // so it must be inside the window as well as the current time.
+    let plant = io::get_plant(&treasure_key_encode)?;
+    let now = chrono::Utc::now().timestamp();
+    for time in &[now, timestamp as i64] {
+        if plant
+            .not_before
+            .map_or(false, |not_before| *time < not_before)
+        {
+            return Err(GeonftError::FileError(format!(
+                "Treasure can't be claimed yet"
+            )));
+        }
+        if plant.not_after.map_or(false, |not_after| *time > not_after) {
+            return Err(GeonftError::FileError(format!(
+                "Treasure can no longer be claimed"
+            )));
+        }
+    }
+
     let account_key_decode = crypto::decode_account_public_key(&claim_info.account_public_key)?;
     let treasure_signature = crypto::decode_signature(&claim_info.treasure_signature)?;
     let account_signature = crypto::decode_signature(&claim_info.account_signature)?;
@@ -107,9 +260,38 @@ pub fn claim_treasure_with_key(claim_info: Json<ClaimRequest>) -> Result<Json<Cl
     crypto::verify_claim_request_for_account(
         &account_key_decode,
         &treasure_key_decode,
+        timestamp,
         &account_signature,
     )?;
 
+    match (&claim_info.wallet, &claim_info.wallet_signature) {
+        (Some(wallet), Some(wallet_signature)) => {
+            let wallet = crypto::decode_wallet_address(wallet)?;
+            let wallet_signature = crypto::decode_signature(wallet_signature)?;
+            crypto::verify_claim_wallet_for_account(
+                &account_key_decode,
+                &treasure_key_decode,
+                &wallet,
+                &wallet_signature,
+            )?;
+        }
+        (None, None) => {}
+        _ => {
+            return Err(GeonftError::DecodeError(format!(
+                "wallet and wallet signature must be given together"
+            )))
+        }
+    }
+
+    let account_key_encode = crypto::encode_account_public_key(&account_key_decode)?;
+
+    // Recorded before the claim so geonft_sync never sees a claim without its wallet
+    if claim_info.wallet.is_none() {
+        if let Some(link) = io::get_account_wallet(&account_key_encode)? {
+            io::record_claim_wallet(&treasure_key_encode, &link.wallet)?;
+        }
+    }
+
     let filename = format!("{}/{key}", io::CLAIM_DIR, key = treasure_key_encode);
     fs::create_dir_all(io::CLAIM_DIR)?;
 
@@ -117,9 +299,218 @@ pub fn claim_treasure_with_key(claim_info: Json<ClaimRequest>) -> Result<Json<Cl
     let file = BufWriter::new(file);
     serde_json::to_writer(file, &claim_info.0)?;
 
+    io::record_account_claim(&account_key_encode, &treasure_key_encode)?;
+
+    let planter_key_encode = io::normalize_account_key(&plant.account_public_key)?;
+    leaderboard.record_claim(&account_key_encode, &planter_key_encode);
+    events.publish_claim(&treasure_key_encode, &account_key_encode);
+
     Ok(Json(ClaimResponse))
 }
 
+/// The longest log comment, in characters
+pub const MAX_COMMENT_LEN: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogResponse;
+
+/// Log finding a treasure.
+///
+/// Validation:
+///
+/// - The account key is valid.
+/// - The treasure key is valid.
+/// - The treasure exists.
+/// - The treasure isn't retired.
+/// - The account hasn't already logged the treasure.
+/// - The comment isn't empty or too long.
+/// - The image, if any, is a PNG or JPEG.
+/// - The account signature.
+/// - The treasure signature.
+///
+/// Stores the json to disk under the treasure,
+/// with the encoded account key as the name of the file.
+#[post("/api/log", format = "json", data = "<log_info>")]
+pub fn log_treasure(log_info: Json<LogRequest>) -> Result<Json<LogResponse>> {
+    let treasure_key_decode = crypto::decode_treasure_public_key(&log_info.treasure_public_key)?;
+    let treasure_key_encode = crypto::encode_treasure_public_key(&treasure_key_decode)?;
+
+    let account_key_decode = crypto::decode_account_public_key(&log_info.account_public_key)?;
+    let account_key_encode = crypto::encode_account_public_key(&account_key_decode)?;
+
+    let treasure_signature = crypto::decode_signature(&log_info.treasure_signature)?;
+    let account_signature = crypto::decode_signature(&log_info.account_signature)?;
+
+    let filename = format!("{}/{}", io::PLANT_DIR, treasure_key_encode);
+    if !Path::new(&filename).is_file() {
+        return Err(GeonftError::FileError(format!("Treasure doesn't exist")));
+    }
+
+    if io::is_retired(&treasure_key_encode) {
+        return Err(GeonftError::FileError(format!("Treasure is retired")));
+    }
+
+    if io::has_log(&treasure_key_encode, &account_key_encode) {
+        return Err(GeonftError::FileError(format!("Treasure already logged")));
+    }
+
+    if log_info.comment.trim().is_empty() {
+        return Err(GeonftError::DecodeError(format!("log comment is empty")));
+    }
+    if log_info.comment.chars().count() > MAX_COMMENT_LEN {
+        return Err(GeonftError::DecodeError(format!("log comment is too long")));
+    }
+
+    let image_hash = match &log_info.image {
+        Some(image) => {
+            images::decode_image(image)?;
+            crypto::get_hash(image)?
+        }
+        None => String::new(),
+    };
+
+    crypto::verify_log_request_for_treasure(
+        &treasure_key_decode,
+        &account_key_decode,
+        &log_info.comment,
+        image_hash.as_bytes(),
+        &treasure_signature,
+    )?;
+
+    crypto::verify_log_request_for_account(
+        &account_key_decode,
+        &treasure_key_decode,
+        &log_info.comment,
+        image_hash.as_bytes(),
+        &account_signature,
+    )?;
+
+    io::record_log(&treasure_key_encode, &account_key_encode, &log_info.0)?;
+
+    Ok(Json(LogResponse))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RetireResponse;
+
+/// Retire a treasure, so it can't be claimed.
+///
+/// Validation:
+///
+/// - The account key is valid.
+/// - The treasure key is valid.
+/// - The treasure exists.
+/// - The treasure isn't already retired.
+/// - The treasure hasn't been claimed.
+/// - The account planted the treasure.
+/// - The account signature.
+#[post("/api/retire", format = "json", data = "<retire_info>")]
+pub fn retire_treasure(
+    retire_info: Json<RetireRequest>,
+    events: &State<EventBus>,
+) -> Result<Json<RetireResponse>> {
+    let treasure_key_decode = crypto::decode_treasure_public_key(&retire_info.treasure_public_key)?;
+    let treasure_key_encode = crypto::encode_treasure_public_key(&treasure_key_decode)?;
+
+    let account_key_decode = crypto::decode_account_public_key(&retire_info.account_public_key)?;
+    let account_key_encode = crypto::encode_account_public_key(&account_key_decode)?;
+    let account_signature = crypto::decode_signature(&retire_info.account_signature)?;
+
+    let filename = format!("{}/{}", io::PLANT_DIR, treasure_key_encode);
+    if !Path::new(&filename).is_file() {
+        return Err(GeonftError::FileError(format!("Treasure doesn't exist")));
+    }
+
+    if io::is_retired(&treasure_key_encode) {
+        return Err(GeonftError::FileError(format!(
+            "Treasure is already retired"
+        )));
+    }
+
+    let claim_filename = format!("{}/{}", io::CLAIM_DIR, treasure_key_encode);
+    if Path::new(&claim_filename).is_file() {
+        return Err(GeonftError::FileError(format!(
+            "Treasure is already claimed"
+        )));
+    }
+
+    let plant = io::get_plant(&treasure_key_encode)?;
+    if io::normalize_account_key(&plant.account_public_key)? != account_key_encode {
+        return Err(GeonftError::DecodeError(format!(
+            "Only the planter can retire a treasure"
+        )));
+    }
+
+    crypto::verify_retire_request_for_account(
+        &account_key_decode,
+        &treasure_key_decode,
+        &account_signature,
+    )?;
+
+    let filename = format!("{}/{key}", io::RETIRE_DIR, key = treasure_key_encode);
+    fs::create_dir_all(io::RETIRE_DIR)?;
+
+    let file = File::create(filename)?;
+    let file = BufWriter::new(file);
+    serde_json::to_writer(file, &retire_info.0)?;
+
+    events.publish_retire(&treasure_key_encode, &account_key_encode);
+
+    Ok(Json(RetireResponse))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LinkWalletResponse;
+
+/// Link a Solana wallet to an account.
+///
+/// Validation:
+///
+/// - The account key is valid.
+/// - The wallet address is valid.
+/// - The timestamp is close to the current time,
+///   and newer than the account's current link.
+/// - The account signature.
+/// - The wallet signature.
+///
+/// If the checks pass then the link replaces the account's previous one.
+/// Later claims by the account are minted to this wallet.
+#[post("/api/link-wallet", format = "json", data = "<link_info>")]
+pub fn link_wallet(link_info: Json<LinkWalletRequest>) -> Result<Json<LinkWalletResponse>> {
+    let account_key_decode = crypto::decode_account_public_key(&link_info.account_public_key)?;
+    let account_key_encode = crypto::encode_account_public_key(&account_key_decode)?;
+
+    let wallet = crypto::decode_wallet_address(&link_info.wallet)?;
+    let account_signature = crypto::decode_signature(&link_info.account_signature)?;
+    let wallet_signature = base64::decode(&link_info.wallet_signature)?;
+
+    check_clock_skew(link_info.timestamp, "wallet link")?;
+
+    if let Some(current) = io::get_account_wallet(&account_key_encode)? {
+        if current.timestamp >= link_info.timestamp {
+            return Err(GeonftError::DecodeError(format!(
+                "wallet link is older than the account's current link"
+            )));
+        }
+    }
+
+    crypto::verify_link_wallet_for_account(
+        &account_key_decode,
+        &wallet,
+        link_info.timestamp,
+        &account_signature,
+    )?;
+
+    let message = crypto::link_wallet_message(&account_key_encode, link_info.timestamp);
+    let wallet_key = ed25519_dalek::PublicKey::from_bytes(&wallet)?;
+    let wallet_signature = ed25519_dalek::Signature::try_from(&wallet_signature[..])?;
+    wallet_key.verify(message.as_bytes(), &wallet_signature)?;
+
+    io::record_account_wallet(&account_key_encode, &link_info.0)?;
+
+    Ok(Json(LinkWalletResponse))
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TreasureRequest {
     treasure_public_key: String,
@@ -148,3 +539,283 @@ pub fn treasure_exists(treasure: Json<TreasureRequest>) -> Result<Json<TreasureR
         }))
     }
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountTreasuresResponse {
+    account_public_key: String,
+    planted_count: usize,
+    claimed_count: usize,
+    planted: Vec<AccountTreasure>,
+    claimed: Vec<AccountTreasure>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountTreasure {
+    treasure_public_key: String,
+    /// RFC 3339 time of the plant or claim
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<TreasureDetails>,
+}
+
+/// The treasures an account has planted and claimed, newest first.
+#[get("/api/accounts/<public_key>/treasures")]
+pub fn account_treasures(public_key: &str) -> Result<Json<AccountTreasuresResponse>> {
+    let account_key_encode = io::normalize_account_key(public_key)?;
+    let treasures = io::get_account_treasures(&account_key_encode)?;
+
+    let to_response = |treasures: Vec<io::TreasureTime>| -> Result<Vec<AccountTreasure>> {
+        treasures
+            .into_iter()
+            .map(|t| {
+                Ok(AccountTreasure {
+                    details: io::get_plant(&t.public_key)?.details,
+                    treasure_public_key: t.public_key,
+                    time: t.time.to_rfc3339(),
+                })
+            })
+            .collect()
+    };
+
+    Ok(Json(AccountTreasuresResponse {
+        account_public_key: account_key_encode,
+        planted_count: treasures.planted.len(),
+        claimed_count: treasures.claimed.len(),
+        planted: to_response(treasures.planted)?,
+        claimed: to_response(treasures.claimed)?,
+    }))
+}
+
+/// The accounts with the most treasures claimed, planted,
+/// and planted then claimed by others.
+///
+/// `window` is one of `day`, `week`, `month` or `all_time`, the default.
+#[get("/api/leaderboard?<window>&<limit>")]
+pub fn leaderboard_rankings(
+    window: Option<&str>,
+    limit: Option<usize>,
+    leaderboard: &State<Leaderboard>,
+) -> Result<Json<Rankings>> {
+    let window = Window::from_param(window)
+        .ok_or_else(|| GeonftError::DecodeError(format!("unknown leaderboard window")))?;
+    let limit = limit
+        .unwrap_or(leaderboard::DEFAULT_LIMIT)
+        .min(leaderboard::MAX_LIMIT);
+
+    Ok(Json(leaderboard.rankings(window, limit)))
+}
+
+/// The most treasures returned by one `/api/treasures` call
+pub const MAX_TREASURES_LIMIT: usize = 100;
+pub const DEFAULT_TREASURES_LIMIT: usize = 10;
+
+#[derive(Serialize)]
+pub struct TreasuresResponse {
+    treasures: Vec<TreasureTemplateData>,
+    /// Pass as `cursor` to get the next page
+    next_cursor: Option<String>,
+}
+
+/// Planted treasures, newest first.
+///
+/// - `cursor` is the `next_cursor` of a previous response.
+/// - `status` is `claimed` or `unclaimed`.
+/// - `planted_by` is an account public key.
+/// - `since` is an RFC 3339 time.
+#[get("/api/treasures?<cursor>&<limit>&<status>&<planted_by>&<since>")]
+pub fn treasures(
+    cursor: Option<&str>,
+    limit: Option<usize>,
+    status: Option<&str>,
+    planted_by: Option<&str>,
+    since: Option<&str>,
+) -> Result<Json<TreasuresResponse>> {
+    let status = match status {
+        None => None,
+        Some("claimed") => Some(io::ClaimStatus::Claimed),
+        Some("unclaimed") => Some(io::ClaimStatus::Unclaimed),
+        Some(status) => {
+            return Err(GeonftError::DecodeError(format!(
+                "unknown treasure status {}",
+                status
+            )))
+        }
+    };
+
+    let since = match since {
+        Some(since) => Some(
+            chrono::DateTime::parse_from_rfc3339(since)
+                .map_err(|e| GeonftError::DecodeError(format!("{}", e)))?
+                .with_timezone(&chrono::Local),
+        ),
+        None => None,
+    };
+
+    let query = io::TreasureQuery {
+        cursor: cursor.map(|c| c.parse()).transpose()?,
+        limit: limit
+            .unwrap_or(DEFAULT_TREASURES_LIMIT)
+            .max(1)
+            .min(MAX_TREASURES_LIMIT),
+        status,
+        planted_by: planted_by.map(io::normalize_account_key).transpose()?,
+        since,
+    };
+
+    let page = io::query_treasures(&query)?;
+
+    let treasures = page
+        .treasures
+        .iter()
+        .map(|t| io::load_treasure_data(&t.public_key))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(Json(TreasuresResponse {
+        treasures,
+        next_cursor: page.next_cursor.map(|c| c.to_string()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data;
+    use ed25519_dalek::Signer;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::Client;
+
+    fn client() -> Client {
+        test_data::client(routes![link_wallet])
+    }
+
+    struct Linker {
+        account: crypto::Keypair,
+        account_public_key: String,
+        wallet: ed25519_dalek::Keypair,
+    }
+
+    impl Linker {
+        fn new() -> Linker {
+            let mnemonic = crypto::new_account_mnemonic(&mut rand::thread_rng()).unwrap();
+            let account = crypto::keypair_from_account_mnemonic(&mnemonic).unwrap();
+            let account_public_key = crypto::encode_account_public_key(&account.public).unwrap();
+            let wallet = ed25519_dalek::Keypair::generate(&mut rand::rngs::OsRng);
+
+            Linker {
+                account,
+                account_public_key,
+                wallet,
+            }
+        }
+
+        fn request(&self, timestamp: u64) -> LinkWalletRequest {
+            let wallet = self.wallet.public.to_bytes();
+            let account_signature =
+                crypto::sign_link_wallet_for_account(&self.account.secret, &wallet, timestamp)
+                    .unwrap();
+            let message = crypto::link_wallet_message(&self.account_public_key, timestamp);
+            let wallet_signature = self.wallet.sign(message.as_bytes());
+
+            LinkWalletRequest {
+                account_public_key: self.account_public_key.clone(),
+                wallet: bs58::encode(wallet).into_string(),
+                timestamp,
+                account_signature: crypto::encode_signature(&account_signature).unwrap(),
+                wallet_signature: base64::encode(wallet_signature.to_bytes()),
+            }
+        }
+    }
+
+    fn post_link(client: &Client, link: &LinkWalletRequest) -> Status {
+        client
+            .post("/api/link-wallet")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(link).unwrap())
+            .dispatch()
+            .status()
+    }
+
+    fn now() -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+
+    #[test]
+    fn link_wallet_signatures() {
+        let client = client();
+        let linker = Linker::new();
+        let now = now();
+
+        // The account signed a different timestamp
+        let mut link = linker.request(now);
+        link.account_signature = linker.request(now - 1).account_signature;
+        assert_ne!(post_link(&client, &link), Status::Ok);
+
+        // The wallet signed a different timestamp
+        let mut link = linker.request(now);
+        link.wallet_signature = linker.request(now - 1).wallet_signature;
+        assert_ne!(post_link(&client, &link), Status::Ok);
+
+        // Another wallet signed
+        let mut link = linker.request(now);
+        link.wallet = Linker::new().request(now).wallet;
+        assert_ne!(post_link(&client, &link), Status::Ok);
+
+        assert!(io::get_account_wallet(&linker.account_public_key)
+            .unwrap()
+            .is_none());
+
+        let link = linker.request(now);
+        assert_eq!(post_link(&client, &link), Status::Ok);
+        assert_eq!(
+            io::get_account_wallet(&linker.account_public_key)
+                .unwrap()
+                .unwrap(),
+            link
+        );
+    }
+
+    #[test]
+    fn link_wallet_only_newer() {
+        let client = client();
+        let linker = Linker::new();
+        let now = now();
+
+        let older = linker.request(now - 60);
+        let current = linker.request(now);
+        assert_eq!(post_link(&client, &current), Status::Ok);
+
+        // Replayed, or signed before the current link
+        assert_ne!(post_link(&client, &current), Status::Ok);
+        assert_ne!(post_link(&client, &older), Status::Ok);
+
+        let newer = Linker {
+            wallet: ed25519_dalek::Keypair::generate(&mut rand::rngs::OsRng),
+            ..linker
+        }
+        .request(now + 1);
+        assert_eq!(post_link(&client, &newer), Status::Ok);
+        assert_ne!(post_link(&client, &current), Status::Ok);
+
+        let account_public_key = &newer.account_public_key;
+        assert_eq!(
+            io::get_account_wallet(account_public_key).unwrap().unwrap(),
+            newer
+        );
+    }
+
+    #[test]
+    fn link_wallet_clock_skew() {
+        let client = client();
+        let linker = Linker::new();
+        let now = now();
+
+        assert_ne!(
+            post_link(&client, &linker.request(now - MAX_CLOCK_SKEW - 60)),
+            Status::Ok
+        );
+        assert_ne!(
+            post_link(&client, &linker.request(now + MAX_CLOCK_SKEW + 60)),
+            Status::Ok
+        );
+    }
+}
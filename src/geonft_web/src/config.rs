@@ -0,0 +1,72 @@
+//! Site configuration, read from `Rocket.toml`
+//!
+//! ```toml
+//! [release]
+//! base_url = "https://treasuretree.org"
+//! allowed_origins = ["https://staging.treasuretree.org"]
+//! ```
+
+use anyhow::{bail, Result};
+use geonft_nostd::crypto;
+use rocket::fairing::AdHoc;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::State;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct SiteConfig {
+    /// The origin this site is served from,
+    /// used in the claim URLs of new treasure QR codes.
+    pub base_url: String,
+    /// Other sites whose treasure QR codes can be scanned here.
+    /// The base URL is always allowed.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+impl SiteConfig {
+    fn validate(&mut self) -> Result<()> {
+        let (_, path) = crypto::split_origin(&self.base_url)?;
+        if !path.is_empty() && path != "/" {
+            bail!("base_url must not have a path: {}", self.base_url);
+        }
+        self.base_url = self.base_url.trim_end_matches('/').to_string();
+
+        for origin in &self.allowed_origins {
+            crypto::split_origin(origin)?;
+        }
+        if !self.allowed_origins.contains(&self.base_url) {
+            self.allowed_origins.insert(0, self.base_url.clone());
+        }
+
+        Ok(())
+    }
+}
+
+/// Load and validate the `SiteConfig`, making it available as managed state.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Site config", |rocket| async {
+        let mut config: SiteConfig = match rocket.figment().extract() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("invalid site config: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        if let Err(e) = config.validate() {
+            error!("invalid site config: {}", e);
+            return Err(rocket);
+        }
+
+        info!("site base URL: {}", config.base_url);
+
+        Ok(rocket.manage(config))
+    })
+}
+
+/// The site config, for JS to build and check treasure URLs.
+#[get("/api/site-config")]
+pub fn site_config(config: &State<SiteConfig>) -> Json<SiteConfig> {
+    Json(config.inner().clone())
+}
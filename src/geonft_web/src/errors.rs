@@ -36,3 +36,9 @@ impl From<base64::DecodeError> for GeonftError {
         GeonftError::DecodeError(format!("{}", e))
     }
 }
+
+impl From<ed25519_dalek::SignatureError> for GeonftError {
+    fn from(e: ed25519_dalek::SignatureError) -> Self {
+        GeonftError::DecodeError(format!("{}", e))
+    }
+}
@@ -0,0 +1,427 @@
+//! Live plant, claim, retire and sync status events
+//!
+//! The API handlers publish to an in-process `EventBus`,
+//! which `/api/events` streams to browsers as server-sent events.
+//! Sync statuses are written by `geonft_sync`, a separate process,
+//! so they are picked up by watching `data/sync-status` for file events.
+//!
+//! Recent events are kept so a reconnecting client
+//! can resume from its `Last-Event-ID`.
+
+use chrono::Local;
+use geonft_shared::io::{self, SyncStatus};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use rocket::fairing::AdHoc;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::{Shutdown, State};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fs;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How many past events are kept for resuming clients
+const HISTORY_LEN: usize = 256;
+
+/// How many events a slow client can fall behind before it is dropped
+const CHANNEL_CAPACITY: usize = 64;
+
+/// How long to let writes to a sync status file settle before reading it
+const SYNC_WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How long to wait before restarting a stopped sync status watcher
+const SYNC_WATCH_RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum EventKind {
+    Plant,
+    Claim,
+    Retire,
+    Sync,
+}
+
+impl EventKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Plant => "plant",
+            EventKind::Claim => "claim",
+            EventKind::Retire => "retire",
+            EventKind::Sync => "sync",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct LiveEvent {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub treasure_public_key: String,
+    pub treasure_url: String,
+    /// The planter, claimer or retirer
+    pub account_public_key: Option<String>,
+    pub sync_status: Option<SyncStatus>,
+    /// RFC 3339
+    pub time: String,
+}
+
+impl LiveEvent {
+    fn to_sse(&self) -> Event {
+        Event::json(self)
+            .event(self.kind.name())
+            .id(self.id.to_string())
+    }
+}
+
+struct History {
+    next_id: u64,
+    events: VecDeque<LiveEvent>,
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<LiveEvent>,
+    history: Arc<Mutex<History>>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        EventBus {
+            sender,
+            history: Arc::new(Mutex::new(History {
+                // Ids start from the clock so they keep increasing across restarts,
+                // and a stale Last-Event-ID doesn't hide new events.
+                next_id: Local::now().timestamp_millis() as u64,
+                events: VecDeque::with_capacity(HISTORY_LEN),
+            })),
+        }
+    }
+
+    pub fn publish_plant(&self, treasure_public_key: &str, planter: &str) {
+        self.publish(EventKind::Plant, treasure_public_key, Some(planter), None);
+    }
+
+    pub fn publish_claim(&self, treasure_public_key: &str, claimer: &str) {
+        self.publish(EventKind::Claim, treasure_public_key, Some(claimer), None);
+    }
+
+    pub fn publish_retire(&self, treasure_public_key: &str, planter: &str) {
+        self.publish(EventKind::Retire, treasure_public_key, Some(planter), None);
+    }
+
+    pub fn publish_sync(&self, treasure_public_key: &str, status: SyncStatus) {
+        self.publish(EventKind::Sync, treasure_public_key, None, Some(status));
+    }
+
+    fn publish(
+        &self,
+        kind: EventKind,
+        treasure_public_key: &str,
+        account_public_key: Option<&str>,
+        sync_status: Option<SyncStatus>,
+    ) {
+        let mut history = self.history.lock().expect("lock");
+
+        let event = LiveEvent {
+            id: history.next_id,
+            kind,
+            treasure_public_key: treasure_public_key.to_string(),
+            treasure_url: format!("treasure/{}", treasure_public_key),
+            account_public_key: account_public_key.map(str::to_string),
+            sync_status,
+            time: Local::now().to_rfc3339(),
+        };
+
+        history.next_id += 1;
+        if history.events.len() == HISTORY_LEN {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+
+        // Sent under the lock so subscribers see events in id order,
+        // with none missed between the history and the channel.
+        // An error just means nobody is listening.
+        let _ = self.sender.send(event);
+    }
+
+    /// A receiver for every event published from now on.
+    pub fn receiver(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+
+    /// The kept events after `last_event_id`,
+    /// and a receiver for everything published afterwards.
+    fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+    ) -> (Vec<LiveEvent>, broadcast::Receiver<LiveEvent>) {
+        let history = self.history.lock().expect("lock");
+        let receiver = self.sender.subscribe();
+
+        let backlog = match last_event_id {
+            Some(last_event_id) => history
+                .events
+                .iter()
+                .filter(|event| event.id > last_event_id)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        (backlog, receiver)
+    }
+}
+
+/// The `Last-Event-ID` header sent by a reconnecting `EventSource`
+pub struct LastEventId(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let id = request
+            .headers()
+            .get_one("Last-Event-ID")
+            .and_then(|id| id.trim().parse().ok());
+
+        Outcome::Success(LastEventId(id))
+    }
+}
+
+/// Plant, claim and sync status events as they happen.
+///
+/// Each event's `event` name is its type,
+/// and its data is a JSON `LiveEvent`.
+#[get("/api/events")]
+pub fn events(
+    bus: &State<EventBus>,
+    last_event_id: LastEventId,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let (backlog, mut receiver) = bus.subscribe(last_event_id.0);
+
+    EventStream! {
+        for event in backlog {
+            yield event.to_sse();
+        }
+
+        loop {
+            let event = select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    // A lagging client reconnects with its Last-Event-ID
+                    // and catches up from the history.
+                    Err(RecvError::Closed) | Err(RecvError::Lagged(_)) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+
+            yield event.to_sse();
+        }
+    }
+}
+
+/// Publish an event whenever a sync status file appears or changes.
+///
+/// Blocks on file events, so this runs on its own thread.
+/// If the watcher stops it is restarted,
+/// and any statuses that changed meanwhile are published.
+fn watch_sync_statuses(bus: EventBus) {
+    let mut known = None;
+
+    loop {
+        match watch_sync_statuses_until_stopped(&bus, &mut known) {
+            Ok(()) => warn!("sync status watcher disconnected, restarting it"),
+            Err(e) => error!("unable to watch sync statuses: {}", e),
+        }
+
+        thread::sleep(SYNC_WATCH_RESTART_DELAY);
+    }
+}
+
+/// Watch sync statuses until the watcher disconnects.
+///
+/// Only the files named in events are read,
+/// except when starting over with `known` statuses,
+/// and after the watcher reports it lost track of events.
+fn watch_sync_statuses_until_stopped(
+    bus: &EventBus,
+    known: &mut Option<HashMap<String, SyncStatus>>,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(io::SYNC_STATUS_DIR)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::watcher(tx, SYNC_WATCH_DEBOUNCE)?;
+    watcher.watch(io::SYNC_STATUS_DIR, RecursiveMode::NonRecursive)?;
+
+    let known = match known {
+        Some(known) => {
+            publish_all_changed(bus, known);
+            known
+        }
+        None => known.get_or_insert(io::get_all_sync_statuses()?),
+    };
+
+    for event in rx {
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Rename(_, path) => {
+                let public_key = match path.file_name().and_then(|name| name.to_str()) {
+                    Some(public_key) => public_key,
+                    None => continue,
+                };
+
+                match io::get_sync_status(public_key) {
+                    Ok(status) => publish_if_changed(bus, known, public_key, status),
+                    Err(e) => warn!("unable to read sync status {}: {}", public_key, e),
+                }
+            }
+            DebouncedEvent::Rescan | DebouncedEvent::Error(..) => {
+                if let DebouncedEvent::Error(e, path) = &event {
+                    warn!("sync status watcher error for {:?}: {}", path, e);
+                }
+
+                publish_all_changed(bus, known);
+            }
+            DebouncedEvent::NoticeWrite(_)
+            | DebouncedEvent::NoticeRemove(_)
+            | DebouncedEvent::Chmod(_)
+            | DebouncedEvent::Remove(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn publish_all_changed(bus: &EventBus, known: &mut HashMap<String, SyncStatus>) {
+    match io::get_all_sync_statuses() {
+        Ok(statuses) => {
+            for (public_key, status) in statuses {
+                publish_if_changed(bus, known, &public_key, status);
+            }
+        }
+        Err(e) => error!("unable to read sync statuses: {}", e),
+    }
+}
+
+fn publish_if_changed(
+    bus: &EventBus,
+    known: &mut HashMap<String, SyncStatus>,
+    public_key: &str,
+    status: SyncStatus,
+) {
+    if known.get(public_key) != Some(&status) {
+        bus.publish_sync(public_key, status);
+        known.insert(public_key.to_string(), status);
+    }
+}
+
+/// Manage the `EventBus` and start watching sync statuses once launched.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Event bus", |rocket| async {
+        rocket
+            .manage(EventBus::new())
+            .attach(AdHoc::on_liftoff("Sync status events", |rocket| {
+                Box::pin(async move {
+                    let bus = rocket.state::<EventBus>().expect("event bus").clone();
+                    thread::spawn(move || watch_sync_statuses(bus));
+                })
+            }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    fn ids(events: &[LiveEvent]) -> Vec<u64> {
+        events.iter().map(|event| event.id).collect()
+    }
+
+    #[test]
+    fn history_after_last_event_id() {
+        let bus = EventBus::new();
+        bus.publish_plant("gtp1a", "gap1a");
+        bus.publish_claim("gtp1a", "gap1b");
+        bus.publish_sync("gtp1a", SyncStatus::ClaimSynced);
+
+        let (all, _) = bus.subscribe(Some(0));
+        assert_eq!(all.len(), 3);
+        assert!(all[0].id < all[1].id && all[1].id < all[2].id);
+        assert_eq!(all[1].kind, EventKind::Claim);
+        assert_eq!(all[1].account_public_key.as_deref(), Some("gap1b"));
+        assert_eq!(all[2].sync_status, Some(SyncStatus::ClaimSynced));
+
+        let (backlog, _) = bus.subscribe(Some(all[0].id));
+        assert_eq!(ids(&backlog), ids(&all[1..]));
+
+        let (backlog, _) = bus.subscribe(Some(all[2].id));
+        assert!(backlog.is_empty());
+
+        // A new client only gets what is published from now on
+        let (backlog, mut receiver) = bus.subscribe(None);
+        assert!(backlog.is_empty());
+        bus.publish_retire("gtp1a", "gap1a");
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.kind, EventKind::Retire);
+        assert_eq!(event.id, all[2].id + 1);
+    }
+
+    #[test]
+    fn history_keeps_latest() {
+        let bus = EventBus::new();
+        for _ in 0..HISTORY_LEN + 10 {
+            bus.publish_plant("gtp1a", "gap1a");
+        }
+
+        let (backlog, _) = bus.subscribe(Some(0));
+        assert_eq!(backlog.len(), HISTORY_LEN);
+        let (last, _) = bus.subscribe(Some(backlog[HISTORY_LEN - 2].id));
+        assert_eq!(ids(&last), vec![backlog[HISTORY_LEN - 1].id]);
+    }
+
+    #[test]
+    fn stream_resumes_from_last_event_id() {
+        let bus = EventBus::new();
+        bus.publish_plant("gtp1a", "gap1a");
+        bus.publish_plant("gtp1b", "gap1a");
+        bus.publish_claim("gtp1a", "gap1b");
+        let (all, _) = bus.subscribe(Some(0));
+
+        let rocket = rocket::build().manage(bus).mount("/", routes![events]);
+        let client = Client::tracked(rocket).unwrap();
+        // End the stream once the backlog is sent
+        client.rocket().shutdown().notify();
+
+        let body = client
+            .get("/api/events")
+            .header(rocket::http::Header::new(
+                "Last-Event-ID",
+                all[0].id.to_string(),
+            ))
+            .dispatch()
+            .into_string()
+            .unwrap();
+
+        let sent_ids: Vec<String> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("id:"))
+            .map(str::to_string)
+            .collect();
+        assert_eq!(sent_ids, vec![all[1].id.to_string(), all[2].id.to_string()]);
+        assert!(body.contains("\nevent:claim\n"));
+    }
+}
@@ -0,0 +1,338 @@
+//! Atom and JSON feeds of planted, claimed and retired treasures
+//!
+//! Each plant, claim and retirement is a feed entry,
+//! linking to the treasure page, with the treasure image as an enclosure,
+//! and the coordinates from the image's EXIF data if it has any.
+
+use crate::config::SiteConfig;
+use crate::errors::Result;
+use crate::images;
+use chrono::{DateTime, Local};
+use geonft_shared::io::{self, PlantClaim};
+use rocket::http::ContentType;
+use rocket::serde::Serialize;
+use rocket::State;
+
+/// How many of the latest events are in the feeds
+const FEED_ENTRIES: usize = 50;
+
+static FEED_TITLE: &str = "TreasureTree";
+
+struct FeedEntry {
+    id: String,
+    title: String,
+    url: String,
+    image_url: String,
+    image_type: ContentType,
+    account_public_key: String,
+    time: DateTime<Local>,
+    coordinates: Option<(f64, f64)>,
+}
+
+fn load_entries(base_url: &str) -> Result<Vec<FeedEntry>> {
+    let events = io::get_all_plants_and_claims_time_sorted()?;
+
+    let mut entries = Vec::new();
+
+    for (event, treasure) in events.into_iter().rev().take(FEED_ENTRIES) {
+        let public_key = treasure.public_key;
+        let abbrev = geonft_nostd::abbrev_pubkey(&public_key);
+        let url = format!("{}/treasure/{}", base_url, public_key);
+
+        let plant = io::get_plant(&public_key)?;
+        let image = base64::decode(&plant.image)?;
+
+        let (id, title, account_public_key) = match event {
+            PlantClaim::Plant => (
+                format!("{}#plant", url),
+                format!("Treasure {} planted", abbrev),
+                plant.account_public_key,
+            ),
+            PlantClaim::Claim => (
+                format!("{}#claim", url),
+                format!("Treasure {} claimed", abbrev),
+                io::get_claim(&public_key)?.account_public_key,
+            ),
+            PlantClaim::Retire => (
+                format!("{}#retire", url),
+                format!("Treasure {} retired", abbrev),
+                io::get_retire(&public_key)?.account_public_key,
+            ),
+        };
+
+        entries.push(FeedEntry {
+            id,
+            title,
+            url,
+            image_url: format!("{}/treasure-images/{}", base_url, public_key),
+            image_type: images::detect_image_type(&image).unwrap_or(ContentType::Binary),
+            account_public_key,
+            time: treasure.time,
+            coordinates: images::gps_coordinates(&image),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn entry_text(entry: &FeedEntry) -> String {
+    let mut text = format!("{} by {}.", entry.title, entry.account_public_key);
+    if let Some((latitude, longitude)) = entry.coordinates {
+        text += &format!(" Photographed at {:.6}, {:.6}.", latitude, longitude);
+    }
+    text
+}
+
+#[get("/feed.atom")]
+pub fn atom_feed(config: &State<SiteConfig>) -> Result<(ContentType, String)> {
+    let base_url = &config.base_url;
+    let entries = load_entries(base_url)?;
+    let updated = entries
+        .first()
+        .map(|entry| entry.time)
+        .unwrap_or_else(Local::now);
+
+    let mut xml = String::new();
+
+    xml += "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n";
+    xml += "<feed xmlns=\"http://www.w3.org/2005/Atom\" \
+            xmlns:georss=\"http://www.georss.org/georss\">\n";
+    xml += &format!("<title>{}</title>\n", escape_xml(FEED_TITLE));
+    xml += &format!("<id>{}/feed.atom</id>\n", escape_xml(base_url));
+    xml += &format!(
+        "<link rel=\"self\" href=\"{}/feed.atom\"/>\n",
+        escape_xml(base_url)
+    );
+    xml += &format!("<link href=\"{}/\"/>\n", escape_xml(base_url));
+    xml += &format!("<updated>{}</updated>\n", updated.to_rfc3339());
+
+    for entry in &entries {
+        xml += "<entry>\n";
+        xml += &format!("<id>{}</id>\n", escape_xml(&entry.id));
+        xml += &format!("<title>{}</title>\n", escape_xml(&entry.title));
+        xml += &format!("<updated>{}</updated>\n", entry.time.to_rfc3339());
+        xml += &format!(
+            "<author><name>{}</name></author>\n",
+            escape_xml(&entry.account_public_key)
+        );
+        xml += &format!("<link href=\"{}\"/>\n", escape_xml(&entry.url));
+        xml += &format!(
+            "<link rel=\"enclosure\" href=\"{}\" type=\"{}\"/>\n",
+            escape_xml(&entry.image_url),
+            entry.image_type
+        );
+        xml += &format!("<summary>{}</summary>\n", escape_xml(&entry_text(entry)));
+        if let Some((latitude, longitude)) = entry.coordinates {
+            xml += &format!(
+                "<georss:point>{:.6} {:.6}</georss:point>\n",
+                latitude, longitude
+            );
+        }
+        xml += "</entry>\n";
+    }
+
+    xml += "</feed>\n";
+
+    Ok((ContentType::new("application", "atom+xml"), xml))
+}
+
+/// A JSON Feed 1.1 document
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct JsonFeed {
+    version: &'static str,
+    title: &'static str,
+    home_page_url: String,
+    feed_url: String,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    content_text: String,
+    date_published: String,
+    image: String,
+    authors: Vec<JsonFeedAuthor>,
+    attachments: Vec<JsonFeedAttachment>,
+    /// Extension with the coordinates, if known
+    #[serde(rename = "_geo", skip_serializing_if = "Option::is_none")]
+    geo: Option<JsonFeedGeo>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct JsonFeedAuthor {
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct JsonFeedAttachment {
+    url: String,
+    mime_type: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct JsonFeedGeo {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[get("/feed.json")]
+pub fn json_feed(config: &State<SiteConfig>) -> Result<(ContentType, String)> {
+    let base_url = &config.base_url;
+    let entries = load_entries(base_url)?;
+
+    let items = entries
+        .into_iter()
+        .map(|entry| JsonFeedItem {
+            content_text: entry_text(&entry),
+            id: entry.id,
+            url: entry.url,
+            title: entry.title,
+            date_published: entry.time.to_rfc3339(),
+            image: entry.image_url.clone(),
+            authors: vec![JsonFeedAuthor {
+                name: entry.account_public_key,
+            }],
+            attachments: vec![JsonFeedAttachment {
+                url: entry.image_url,
+                mime_type: entry.image_type.to_string(),
+            }],
+            geo: entry.coordinates.map(|(latitude, longitude)| JsonFeedGeo {
+                latitude,
+                longitude,
+            }),
+        })
+        .collect();
+
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: FEED_TITLE,
+        home_page_url: format!("{}/", base_url),
+        feed_url: format!("{}/feed.json", base_url),
+        items,
+    };
+
+    let json = serde_json::to_string(&feed)?;
+
+    Ok((ContentType::new("application", "feed+json"), json))
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, account_key, treasure_key};
+    use rocket::http::Status;
+    use rocket::serde::json::Value;
+
+    #[test]
+    fn escape_xml_markup() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_xml("gtp1abc"), "gtp1abc");
+    }
+
+    #[test]
+    fn json_feed_newest_first() {
+        let client = test_data::client(routes![json_feed]);
+        let (first, second) = (treasure_key(), treasure_key());
+        let (planter, claimer) = (account_key(), account_key());
+        let start = test_data::now() - 60;
+
+        test_data::plant(&first, &planter, start);
+        test_data::plant(&second, &planter, start + 1);
+        test_data::claim(&first, &claimer, start + 2);
+        test_data::retire(&second, &planter, start + 3);
+
+        let response = client.get("/feed.json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "feed+json"))
+        );
+        let feed: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+        // Other tests may add treasures, so only check ours
+        let items: Vec<(&str, i64)> = feed["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                let published = item["date_published"].as_str().unwrap();
+                let published = DateTime::parse_from_rfc3339(published).unwrap();
+                (item["id"].as_str().unwrap(), published.timestamp())
+            })
+            .filter(|(id, _)| id.contains(&first) || id.contains(&second))
+            .collect();
+        let url =
+            |key: &str, event: &str| format!("{}/treasure/{}#{}", test_data::BASE_URL, key, event);
+        assert_eq!(
+            items,
+            vec![
+                (url(&second, "retire").as_str(), start + 3),
+                (url(&first, "claim").as_str(), start + 2),
+                (url(&second, "plant").as_str(), start + 1),
+                (url(&first, "plant").as_str(), start),
+            ]
+        );
+
+        let claim = feed["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["id"] == url(&first, "claim").as_str())
+            .unwrap();
+        assert_eq!(claim["authors"][0]["name"], claimer.as_str());
+        assert_eq!(claim["attachments"][0]["mime_type"], "image/png");
+        assert!(claim.get("_geo").is_none());
+    }
+
+    #[test]
+    fn atom_feed_updated() {
+        let client = test_data::client(routes![atom_feed]);
+        // Newer than any other test's records
+        let time = test_data::now() + 60;
+        test_data::plant(&treasure_key(), &account_key(), time);
+
+        let response = client.get("/feed.atom").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "atom+xml"))
+        );
+        let xml = response.into_string().unwrap();
+
+        // The feed was updated when its newest entry was
+        let updated: Vec<i64> = xml
+            .lines()
+            .filter_map(|line| line.strip_prefix("<updated>"))
+            .filter_map(|line| line.strip_suffix("</updated>"))
+            .map(|updated| DateTime::parse_from_rfc3339(updated).unwrap().timestamp())
+            .collect();
+        assert!(updated.len() >= 2);
+        assert_eq!(updated[..2], [time, time]);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::errors::{GeonftError, Result};
+use exif::{Exif, In, Tag, Value};
 use image::ImageFormat;
 use rocket::http::ContentType;
+use std::io::Cursor;
 use std::str;
 
 pub fn detect_image_type(data: &[u8]) -> Option<ContentType> {
@@ -19,3 +22,48 @@ pub fn detect_image_type(data: &[u8]) -> Option<ContentType> {
         },
     }
 }
+
+/// Decode a base64 photo uploaded with a log.
+///
+/// Only PNG and JPEG are accepted:
+/// logs come from any account,
+/// and SVG can carry scripts that would run on our origin.
+pub fn decode_image(encoded: &str) -> Result<(ContentType, Vec<u8>)> {
+    let data = base64::decode(encoded)?;
+    match image::guess_format(&data) {
+        Ok(ImageFormat::Png) => Ok((ContentType::PNG, data)),
+        Ok(ImageFormat::Jpeg) => Ok((ContentType::JPEG, data)),
+        _ => Err(GeonftError::DecodeError(format!("unsupported image type"))),
+    }
+}
+
+/// The latitude and longitude in an image's EXIF GPS tags, in degrees.
+pub fn gps_coordinates(data: &[u8]) -> Option<(f64, f64)> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()?;
+
+    let latitude = gps_degrees(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?;
+    let longitude = gps_degrees(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?;
+
+    Some((latitude, longitude))
+}
+
+/// Convert a degrees, minutes, seconds GPS tag to signed degrees.
+fn gps_degrees(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: u8) -> Option<f64> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let degrees = match &field.value {
+        Value::Rational(dms) if dms.len() >= 3 => {
+            dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0
+        }
+        _ => return None,
+    };
+
+    let ref_field = exif.get_field(ref_tag, In::PRIMARY)?;
+    let negative = match &ref_field.value {
+        Value::Ascii(refs) => refs.first().and_then(|r| r.first()) == Some(&negative_ref),
+        _ => return None,
+    };
+
+    Some(if negative { -degrees } else { degrees })
+}
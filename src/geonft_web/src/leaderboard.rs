@@ -0,0 +1,341 @@
+//! Rankings of accounts by treasures planted and claimed
+//!
+//! The counts are built from `data/plant` and `data/claim` once at startup,
+//! then updated as plants and claims are accepted by the API.
+//! Rolling windows keep the events inside them in a queue,
+//! and forget each event's count as it ages out.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use geonft_shared::io::{self, TreasureTime};
+use rocket::fairing::AdHoc;
+use rocket::serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum Window {
+    Day,
+    Week,
+    Month,
+    AllTime,
+}
+
+impl Window {
+    /// Parse the `window` query parameter, defaulting to all time.
+    pub fn from_param(window: Option<&str>) -> Option<Window> {
+        match window {
+            None | Some("all_time") => Some(Window::AllTime),
+            Some("day") => Some(Window::Day),
+            Some("week") => Some(Window::Week),
+            Some("month") => Some(Window::Month),
+            Some(_) => None,
+        }
+    }
+
+    fn duration(self) -> Option<Duration> {
+        match self {
+            Window::Day => Some(Duration::days(1)),
+            Window::Week => Some(Duration::weeks(1)),
+            Window::Month => Some(Duration::days(30)),
+            Window::AllTime => None,
+        }
+    }
+}
+
+/// The most accounts ranked in each category
+pub const MAX_LIMIT: usize = 100;
+pub const DEFAULT_LIMIT: usize = 10;
+
+const WINDOWS: [Window; 4] = [Window::Day, Window::Week, Window::Month, Window::AllTime];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Category {
+    /// Credited to the account that claimed a treasure
+    Claimed,
+    /// Credited to the account that planted a treasure
+    Planted,
+    /// Credited to the planter when their treasure is claimed
+    PlantsClaimed,
+}
+
+struct Event {
+    time: DateTime<Local>,
+    category: Category,
+    account: String,
+}
+
+/// Counts for one window
+#[derive(Default)]
+struct Tally {
+    /// Events inside a rolling window, oldest first.
+    /// Unused for all-time counts.
+    events: VecDeque<Event>,
+    counts: HashMap<(Category, String), u64>,
+}
+
+impl Tally {
+    /// Count an event, forgetting those it has pushed out of the window.
+    ///
+    /// Events are added in time order.
+    fn add(&mut self, event: Event, window: Window) {
+        self.expire(event.time, window);
+
+        *self
+            .counts
+            .entry((event.category, event.account.clone()))
+            .or_insert(0) += 1;
+
+        if window.duration().is_some() {
+            self.events.push_back(event);
+        }
+    }
+
+    fn expire(&mut self, now: DateTime<Local>, window: Window) {
+        let duration = match window.duration() {
+            Some(duration) => duration,
+            None => return,
+        };
+
+        while let Some(event) = self.events.front() {
+            if now - event.time < duration {
+                break;
+            }
+
+            let event = self.events.pop_front().expect("event");
+            let key = (event.category, event.account);
+            if let Some(count) = self.counts.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn ranking(&self, category: Category, limit: usize) -> Vec<Rank> {
+        let mut ranking: Vec<Rank> = self
+            .counts
+            .iter()
+            .filter(|((c, _), _)| *c == category)
+            .map(|((_, account), count)| Rank {
+                account_public_key: account.clone(),
+                account_public_key_abbrev: geonft_nostd::abbrev_pubkey(account),
+                account_url: format!("account/{}", account),
+                count: *count,
+            })
+            .collect();
+
+        ranking.sort_by(|r1, r2| {
+            r2.count
+                .cmp(&r1.count)
+                .then_with(|| r1.account_public_key.cmp(&r2.account_public_key))
+        });
+        ranking.truncate(limit);
+
+        ranking
+    }
+}
+
+pub struct Leaderboard {
+    tallies: Mutex<HashMap<Window, Tally>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Rank {
+    pub account_public_key: String,
+    pub account_public_key_abbrev: String,
+    pub account_url: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Rankings {
+    pub window: Window,
+    pub claimed: Vec<Rank>,
+    pub planted: Vec<Rank>,
+    pub plants_claimed: Vec<Rank>,
+}
+
+impl Leaderboard {
+    fn empty() -> Leaderboard {
+        Leaderboard {
+            tallies: Mutex::new(WINDOWS.iter().map(|w| (*w, Tally::default())).collect()),
+        }
+    }
+
+    /// Count every plant and claim on disk.
+    pub fn load() -> Result<Leaderboard> {
+        let leaderboard = Leaderboard::empty();
+
+        // Rolling windows need events in time order
+        let mut events = Vec::new();
+
+        for TreasureTime { public_key, time } in io::get_all_planted_treasures()? {
+            let plant = io::get_plant(&public_key)?;
+            let planter = io::normalize_account_key(&plant.account_public_key)?;
+            events.push((time, Category::Planted, planter));
+        }
+
+        for TreasureTime { public_key, time } in io::get_all_claimed_treasures()? {
+            let claim = io::get_claim(&public_key)?;
+            let claimer = io::normalize_account_key(&claim.account_public_key)?;
+            let plant = io::get_plant(&public_key)?;
+            let planter = io::normalize_account_key(&plant.account_public_key)?;
+            events.push((time, Category::Claimed, claimer));
+            events.push((time, Category::PlantsClaimed, planter));
+        }
+
+        events.sort_by_key(|(time, _, _)| *time);
+
+        for (time, category, account) in events {
+            leaderboard.add(time, category, account);
+        }
+
+        Ok(leaderboard)
+    }
+
+    pub fn record_plant(&self, planter: &str) {
+        self.add(Local::now(), Category::Planted, planter.to_string());
+    }
+
+    pub fn record_claim(&self, claimer: &str, planter: &str) {
+        let now = Local::now();
+        self.add(now, Category::Claimed, claimer.to_string());
+        self.add(now, Category::PlantsClaimed, planter.to_string());
+    }
+
+    fn add(&self, time: DateTime<Local>, category: Category, account: String) {
+        let mut tallies = self.tallies.lock().expect("lock");
+        for (window, tally) in tallies.iter_mut() {
+            let event = Event {
+                time,
+                category,
+                account: account.clone(),
+            };
+            tally.add(event, *window);
+        }
+    }
+
+    /// The top `limit` accounts in each category.
+    pub fn rankings(&self, window: Window, limit: usize) -> Rankings {
+        self.rankings_at(Local::now(), window, limit)
+    }
+
+    fn rankings_at(&self, now: DateTime<Local>, window: Window, limit: usize) -> Rankings {
+        let mut tallies = self.tallies.lock().expect("lock");
+        let tally = tallies.get_mut(&window).expect("window");
+        tally.expire(now, window);
+
+        Rankings {
+            window,
+            claimed: tally.ranking(Category::Claimed, limit),
+            planted: tally.ranking(Category::Planted, limit),
+            plants_claimed: tally.ranking(Category::PlantsClaimed, limit),
+        }
+    }
+}
+
+/// Load the `Leaderboard`, making it available as managed state.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Leaderboard", |rocket| async {
+        match Leaderboard::load() {
+            Ok(leaderboard) => Ok(rocket.manage(leaderboard)),
+            Err(e) => {
+                error!("unable to load leaderboard: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn counts(ranks: &[Rank]) -> Vec<(&str, u64)> {
+        ranks
+            .iter()
+            .map(|rank| (rank.account_public_key.as_str(), rank.count))
+            .collect()
+    }
+
+    #[test]
+    fn rolling_window_counts() {
+        let start = Local.timestamp(1_600_000_000, 0);
+        let leaderboard = Leaderboard::empty();
+
+        leaderboard.add(start, Category::Planted, "alice".to_string());
+        leaderboard.add(
+            start + Duration::hours(12),
+            Category::Planted,
+            "alice".to_string(),
+        );
+        leaderboard.add(
+            start + Duration::days(2),
+            Category::Planted,
+            "bob".to_string(),
+        );
+
+        let now = start + Duration::days(2);
+        let day = leaderboard.rankings_at(now, Window::Day, MAX_LIMIT);
+        assert_eq!(counts(&day.planted), vec![("bob", 1)]);
+        let week = leaderboard.rankings_at(now, Window::Week, MAX_LIMIT);
+        assert_eq!(counts(&week.planted), vec![("alice", 2), ("bob", 1)]);
+
+        // An event a window old has aged out
+        let now = start + Duration::days(7) + Duration::hours(12);
+        let week = leaderboard.rankings_at(now, Window::Week, MAX_LIMIT);
+        assert_eq!(counts(&week.planted), vec![("bob", 1)]);
+        let all_time = leaderboard.rankings_at(now, Window::AllTime, MAX_LIMIT);
+        assert_eq!(counts(&all_time.planted), vec![("alice", 2), ("bob", 1)]);
+    }
+
+    #[test]
+    fn add_expires_old_events() {
+        let start = Local.timestamp(1_600_000_000, 0);
+        let leaderboard = Leaderboard::empty();
+
+        for day in 0..60 {
+            leaderboard.add(
+                start + Duration::days(day),
+                Category::Claimed,
+                "alice".to_string(),
+            );
+        }
+
+        let tallies = leaderboard.tallies.lock().expect("lock");
+        assert_eq!(tallies[&Window::Day].events.len(), 1);
+        assert_eq!(tallies[&Window::Week].events.len(), 7);
+        assert_eq!(tallies[&Window::Month].events.len(), 30);
+        assert!(tallies[&Window::AllTime].events.is_empty());
+        assert_eq!(
+            tallies[&Window::AllTime].counts[&(Category::Claimed, "alice".to_string())],
+            60
+        );
+    }
+
+    #[test]
+    fn ranking_order() {
+        let time = Local.timestamp(1_600_000_000, 0);
+        let leaderboard = Leaderboard::empty();
+
+        for account in &["carol", "bob", "alice", "carol", "dave", "carol", "bob"] {
+            leaderboard.add(time, Category::Claimed, account.to_string());
+        }
+        leaderboard.add(time, Category::PlantsClaimed, "erin".to_string());
+
+        let rankings = leaderboard.rankings_at(time, Window::AllTime, 3);
+        // Most first, ties by account key
+        assert_eq!(
+            counts(&rankings.claimed),
+            vec![("carol", 3), ("bob", 2), ("alice", 1)]
+        );
+        assert!(rankings.planted.is_empty());
+        assert_eq!(counts(&rankings.plants_claimed), vec![("erin", 1)]);
+    }
+}
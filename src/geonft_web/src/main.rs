@@ -5,19 +5,30 @@ extern crate rocket_dyn_templates;
 use geonft_nostd::crypto;
 use geonft_request::PlantRequest;
 use geonft_shared::io::{self, TreasureTemplateData};
-use std::fs::{self, DirEntry, File, Metadata};
+use std::fs::File;
 use std::io::BufReader;
 
+use rocket::fairing::AdHoc;
 use rocket::fs::FileServer;
 use rocket::http::ContentType;
+use rocket::response::Redirect;
 use rocket::serde::{json::json, Serialize};
+use rocket::State;
 use rocket_dyn_templates::Template;
 
-use errors::Result;
+use errors::{GeonftError, Result};
 
 mod api;
+mod config;
 mod errors;
+mod events;
+mod feed;
 mod images;
+mod leaderboard;
+mod metadata;
+#[cfg(test)]
+mod test_data;
+mod webhooks;
 
 #[get("/")]
 fn root_page() -> Template {
@@ -29,51 +40,39 @@ fn static_page(page: String) -> Template {
     Template::render(page, json!({}))
 }
 
-#[get("/recent")]
-fn recent_page() -> Result<Template> {
-    fs::create_dir_all(io::PLANT_DIR)?;
-
-    // This nightmare expression collects DirEntrys for every
-    // thing in the directory that is a file,
-    // and extracting the modify time,
-    // while also bubbling any possible errors.
-    // It does the "collect Iter<Item = Result> into Result<Vec>" trick.
-    let mut files = fs::read_dir(io::PLANT_DIR)?
-        // Get the file metadata
-        .map(|dent: std::result::Result<DirEntry, _>| {
-            dent.and_then(|dent| Ok((dent.metadata()?, dent)))
-        })
-        // Only keep entries that are files or errors
-        .filter(|dent: &std::result::Result<(Metadata, DirEntry), _>| {
-            dent.as_ref()
-                .map(|(meta, _)| meta.is_file())
-                .unwrap_or(true)
-        })
-        // Keep modify time for sorting
-        .map(|dent: std::result::Result<(Metadata, DirEntry), _>| {
-            dent.and_then(|(meta, dent)| Ok((meta.modified()?, dent)))
-        })
-        // Collect iter of Result into Result<Vec>,
-        // and return any error.
-        .collect::<std::result::Result<Vec<_>, _>>()?;
-
-    // Sort by time, reversed
-    files.sort_by(|&(time1, _), &(time2, _)| time2.cmp(&time1));
+/// Return an html page of planted treasures, newest first,
+/// with a link to the next page.
+#[get("/recent?<cursor>")]
+fn recent_page(cursor: Option<&str>) -> Result<Template> {
+    let query = io::TreasureQuery {
+        cursor: cursor.map(|c| c.parse()).transpose()?,
+        limit: 10,
+        status: None,
+        planted_by: None,
+        since: None,
+    };
+
+    let page = io::query_treasures(&query)?;
 
     let mut treasures = Vec::new();
 
-    for (_, dent) in files.into_iter().take(10) {
-        let public_key = dent.file_name().into_string().expect("utf-8");
-        let treasure = io::load_treasure_data(&public_key)?;
+    for treasure in page.treasures {
+        let treasure = io::load_treasure_data(&treasure.public_key)?;
         treasures.push(treasure);
     }
 
     #[derive(Serialize)]
     struct TemplateData {
         treasures: Vec<TreasureTemplateData>,
+        next_url: Option<String>,
     }
 
-    let data = TemplateData { treasures };
+    let data = TemplateData {
+        treasures,
+        next_url: page
+            .next_cursor
+            .map(|cursor| format!("recent?cursor={}", cursor)),
+    };
 
     Ok(Template::render("recent", data))
 }
@@ -94,21 +93,83 @@ fn treasure_page(public_key: &str) -> Result<Template> {
     let public_key = crypto::encode_treasure_public_key(&public_key)?;
 
     let treasure = io::load_treasure_data(&public_key)?;
+    let logs = io::load_treasure_logs(&public_key)?;
 
     #[derive(Serialize)]
     struct TemplateData {
         base_href: &'static str,
         treasure: TreasureTemplateData,
+        logs: Vec<io::LogTemplateData>,
     }
 
     let data = TemplateData {
         base_href: "..",
         treasure,
+        logs,
     };
 
     Ok(Template::render("treasure", data))
 }
 
+/// Return an html page listing the treasures
+/// an account has planted and claimed.
+///
+/// `public_key` is bech32 encoded.
+#[get("/account/<public_key>")]
+fn account_page(public_key: &str) -> Result<Template> {
+    let public_key = io::normalize_account_key(public_key)?;
+    let treasures = io::get_account_treasures(&public_key)?;
+
+    let load = |treasures: Vec<io::TreasureTime>| -> Result<Vec<TreasureTemplateData>> {
+        treasures
+            .iter()
+            .map(|t| Ok(io::load_treasure_data(&t.public_key)?))
+            .collect()
+    };
+
+    #[derive(Serialize)]
+    struct TemplateData {
+        base_href: &'static str,
+        public_key: String,
+        public_key_abbrev: String,
+        planted_count: usize,
+        claimed_count: usize,
+        planted: Vec<TreasureTemplateData>,
+        claimed: Vec<TreasureTemplateData>,
+        wallet: Option<String>,
+    }
+
+    let wallet = io::get_account_wallet(&public_key)?.map(|link| link.wallet);
+
+    let data = TemplateData {
+        base_href: "..",
+        public_key_abbrev: geonft_nostd::abbrev_pubkey(&public_key),
+        public_key,
+        planted_count: treasures.planted.len(),
+        claimed_count: treasures.claimed.len(),
+        planted: load(treasures.planted)?,
+        claimed: load(treasures.claimed)?,
+        wallet,
+    };
+
+    Ok(Template::render("account", data))
+}
+
+/// Return an html page ranking accounts by treasures claimed and planted.
+///
+/// `window` is one of `day`, `week`, `month` or `all_time`, the default.
+#[get("/leaderboard?<window>")]
+fn leaderboard_page(
+    window: Option<&str>,
+    leaderboard: &State<leaderboard::Leaderboard>,
+) -> Result<Template> {
+    let window = leaderboard::Window::from_param(window)
+        .ok_or_else(|| GeonftError::DecodeError(format!("unknown leaderboard window")))?;
+    let rankings = leaderboard.rankings(window, leaderboard::DEFAULT_LIMIT);
+
+    Ok(Template::render("leaderboard", rankings))
+}
+
 /// A treasure's image.
 ///
 /// The `public_key` is bech32 encoded.
@@ -130,6 +191,46 @@ fn treasure_image(public_key: &str) -> Result<(ContentType, Vec<u8>)> {
     Ok((content_type, decoded_image))
 }
 
+/// A photo attached to a treasure log.
+///
+/// Both keys are bech32 encoded.
+#[get("/log-images/<treasure_key>/<account_key>")]
+fn log_image(treasure_key: &str, account_key: &str) -> Result<(ContentType, Vec<u8>)> {
+    let treasure_key = crypto::decode_treasure_public_key(&treasure_key)?;
+    let treasure_key = crypto::encode_treasure_public_key(&treasure_key)?;
+    let account_key = io::normalize_account_key(account_key)?;
+
+    let log = io::get_log(&treasure_key, &account_key)?;
+    let encoded_image = log
+        .image
+        .ok_or_else(|| GeonftError::FileError(format!("Log has no image")))?;
+
+    images::decode_image(&encoded_image)
+}
+
+/// The compact claim URL encoded in treasure QR codes.
+///
+/// The secret key is uppercase so the whole URL fits
+/// the QR alphanumeric mode;
+/// redirect to the claim page with the usual lowercase key.
+#[get("/C/<secret_key>")]
+fn compact_claim_redirect(secret_key: &str) -> Result<Redirect> {
+    claim_redirect(secret_key)
+}
+
+/// The compact claim URL as typed by hand, or lowercased by a QR reader.
+#[get("/c/<secret_key>")]
+fn lowercase_claim_redirect(secret_key: &str) -> Result<Redirect> {
+    claim_redirect(secret_key)
+}
+
+fn claim_redirect(secret_key: &str) -> Result<Redirect> {
+    let keypair = crypto::keypair_from_treasure_secret_key(secret_key)?;
+    let secret_key = crypto::encode_treasure_secret_key(&keypair.secret)?;
+
+    Ok(Redirect::to(format!("/claim?key={}", secret_key)))
+}
+
 #[launch]
 fn rocket() -> _ {
     let css_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/static/css");
@@ -139,6 +240,19 @@ fn rocket() -> _ {
 
     rocket::build()
         .attach(Template::fairing())
+        .attach(config::fairing())
+        .attach(leaderboard::fairing())
+        .attach(events::fairing())
+        .attach(webhooks::fairing())
+        .attach(AdHoc::try_on_ignite("Account index", |rocket| async {
+            match io::rebuild_account_index() {
+                Ok(()) => Ok(rocket),
+                Err(e) => {
+                    error!("unable to rebuild account index: {}", e);
+                    Err(rocket)
+                }
+            }
+        }))
         .mount("/css", FileServer::from(css_dir))
         .mount("/js", FileServer::from(js_dir))
         .mount("/images", FileServer::from(images_dir))
@@ -151,9 +265,25 @@ fn rocket() -> _ {
                 recent_page,
                 treasure_page,
                 treasure_image,
+                log_image,
+                account_page,
+                leaderboard_page,
+                compact_claim_redirect,
+                lowercase_claim_redirect,
                 api::plant_treasure_with_key,
                 api::claim_treasure_with_key,
+                api::retire_treasure,
+                api::log_treasure,
+                api::link_wallet,
                 api::treasure_exists,
+                api::account_treasures,
+                api::leaderboard_rankings,
+                api::treasures,
+                metadata::treasure_metadata,
+                events::events,
+                feed::atom_feed,
+                feed::json_feed,
+                config::site_config,
             ],
         )
 }
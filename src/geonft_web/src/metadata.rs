@@ -0,0 +1,300 @@
+//! NFT metadata for treasures
+//!
+//! The JSON follows the Metaplex token metadata standard,
+//! which is a superset of the ERC-721 metadata JSON schema,
+//! so wallets and marketplaces on either chain can display treasures.
+//! The sync daemon records this document's URL on chain with each plant.
+
+use crate::config::SiteConfig;
+use crate::errors::Result;
+use crate::images;
+use chrono::DateTime;
+use geonft_nostd::crypto;
+use geonft_shared::io;
+use rocket::http::ContentType;
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+static SYMBOL: &str = "TREASURE";
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NftMetadata {
+    name: String,
+    symbol: &'static str,
+    description: String,
+    image: String,
+    external_url: String,
+    attributes: Vec<Attribute>,
+    properties: Properties,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Attribute {
+    trait_type: &'static str,
+    value: AttributeValue,
+    /// `date` for unix timestamps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_type: Option<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum AttributeValue {
+    Text(String),
+    Number(f64),
+    Timestamp(i64),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Properties {
+    files: Vec<File>,
+    category: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct File {
+    uri: String,
+    #[serde(rename = "type")]
+    content_type: String,
+}
+
+impl Attribute {
+    fn text(trait_type: &'static str, value: String) -> Attribute {
+        Attribute {
+            trait_type,
+            value: AttributeValue::Text(value),
+            display_type: None,
+        }
+    }
+
+    fn number(trait_type: &'static str, value: f64) -> Attribute {
+        Attribute {
+            trait_type,
+            value: AttributeValue::Number(value),
+            display_type: None,
+        }
+    }
+
+    /// Dates are parsed from the RFC 2822 times in `TreasureTemplateData`.
+    fn date(trait_type: &'static str, value: &str) -> Result<Attribute> {
+        let time = DateTime::parse_from_rfc2822(value)
+            .map_err(|e| anyhow::anyhow!("invalid treasure time {}: {}", value, e))?;
+
+        Ok(Attribute {
+            trait_type,
+            value: AttributeValue::Timestamp(time.timestamp()),
+            display_type: Some("date"),
+        })
+    }
+}
+
+pub fn load_metadata(public_key: &str, base_url: &str) -> Result<NftMetadata> {
+    let treasure = io::load_treasure_data(public_key)?;
+    let plant = io::get_plant(public_key)?;
+    let image_hash = crypto::get_hash(&plant.image)?;
+    let image = base64::decode(&plant.image)?;
+    let content_type = images::detect_image_type(&image).unwrap_or(ContentType::Binary);
+
+    let image_url = format!("{}/{}", base_url, treasure.image_url);
+    let claimed = treasure.claimed_by_url.is_some();
+
+    let mut attributes = vec![
+        Attribute::date("Planted", &treasure.planted_date_time)?,
+        Attribute::text("Planter", treasure.planted_by.clone()),
+    ];
+
+    if claimed {
+        attributes.push(Attribute::date("Claimed", &treasure.claimed_date_time)?);
+        attributes.push(Attribute::text("Claimer", treasure.claimed_by.clone()));
+    }
+
+    if let Some(retired_date_time) = &treasure.retired_date_time {
+        attributes.push(Attribute::date("Retired", retired_date_time)?);
+    }
+
+    let status = if treasure.retired_date_time.is_some() {
+        "retired"
+    } else if claimed {
+        "claimed"
+    } else {
+        "unclaimed"
+    };
+    attributes.push(Attribute::text("Status", status.to_string()));
+
+    if let Some((latitude, longitude)) = images::gps_coordinates(&image) {
+        attributes.push(Attribute::number("Latitude", latitude));
+        attributes.push(Attribute::number("Longitude", longitude));
+    }
+
+    // The same hash the plant signature and the on-chain plant record commit to
+    attributes.push(Attribute::text("Image SHA-256", image_hash));
+
+    Ok(NftMetadata {
+        name: format!("Treasure {}", treasure.public_key_abbrev),
+        symbol: SYMBOL,
+        description: format!(
+            "A TreasureTree treasure, planted by {}. \
+             Whoever finds its QR code in the real world can claim it.",
+            treasure.planted_by
+        ),
+        image: image_url.clone(),
+        external_url: format!("{}/{}", base_url, treasure.public_url),
+        attributes,
+        properties: Properties {
+            files: vec![File {
+                uri: image_url,
+                content_type: content_type.to_string(),
+            }],
+            category: "image",
+        },
+    })
+}
+
+/// The NFT metadata of a planted treasure.
+///
+/// `public_key` is bech32 encoded.
+#[get("/api/treasures/<public_key>/metadata.json")]
+pub fn treasure_metadata(
+    public_key: &str,
+    config: &State<SiteConfig>,
+) -> Result<Json<NftMetadata>> {
+    let public_key = crypto::decode_treasure_public_key(public_key)?;
+    let public_key = crypto::encode_treasure_public_key(&public_key)?;
+
+    Ok(Json(load_metadata(&public_key, &config.base_url)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, account_key, treasure_key};
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::Value;
+
+    fn client() -> Client {
+        test_data::client(routes![treasure_metadata])
+    }
+
+    fn get_metadata(client: &Client, treasure_key: &str) -> Value {
+        let response = client
+            .get(format!("/api/treasures/{}/metadata.json", treasure_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        serde_json::from_str(&response.into_string().unwrap()).unwrap()
+    }
+
+    fn attribute<'a>(metadata: &'a Value, trait_type: &str) -> Option<&'a Value> {
+        metadata["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|attribute| attribute["trait_type"] == trait_type)
+    }
+
+    fn date(metadata: &Value, trait_type: &str) -> Option<i64> {
+        attribute(metadata, trait_type).map(|attribute| {
+            assert_eq!(attribute["display_type"], "date");
+            attribute["value"].as_i64().unwrap()
+        })
+    }
+
+    #[test]
+    fn unclaimed_metadata() {
+        let client = client();
+        let (treasure, planter) = (treasure_key(), account_key());
+        let planted = test_data::now() - 60;
+        test_data::plant(&treasure, &planter, planted);
+
+        let metadata = get_metadata(&client, &treasure);
+        let image_url = format!("{}/treasure-images/{}", test_data::BASE_URL, treasure);
+        assert_eq!(
+            metadata["name"],
+            format!("Treasure {}", geonft_nostd::abbrev_pubkey(&treasure))
+        );
+        assert_eq!(metadata["symbol"], SYMBOL);
+        assert_eq!(metadata["image"], image_url.as_str());
+        assert_eq!(
+            metadata["external_url"],
+            format!("{}/treasure/{}", test_data::BASE_URL, treasure)
+        );
+        assert_eq!(
+            metadata["properties"]["files"][0]["uri"],
+            image_url.as_str()
+        );
+        assert_eq!(metadata["properties"]["files"][0]["type"], "image/png");
+
+        assert_eq!(date(&metadata, "Planted"), Some(planted));
+        assert_eq!(
+            attribute(&metadata, "Planter").unwrap()["value"],
+            planter.as_str()
+        );
+        assert_eq!(
+            attribute(&metadata, "Status").unwrap()["value"],
+            "unclaimed"
+        );
+        assert!(attribute(&metadata, "Claimed").is_none());
+        assert!(attribute(&metadata, "Claimer").is_none());
+        assert!(attribute(&metadata, "Retired").is_none());
+        // No EXIF coordinates in the test image
+        assert!(attribute(&metadata, "Latitude").is_none());
+
+        let image_hash = crypto::get_hash(&test_data::png_image()).unwrap();
+        assert_eq!(
+            attribute(&metadata, "Image SHA-256").unwrap()["value"],
+            image_hash.as_str()
+        );
+    }
+
+    #[test]
+    fn claimed_metadata() {
+        let client = client();
+        let (treasure, claimer) = (treasure_key(), account_key());
+        let planted = test_data::now() - 60;
+        test_data::plant(&treasure, &account_key(), planted);
+        test_data::claim(&treasure, &claimer, planted + 10);
+
+        let metadata = get_metadata(&client, &treasure);
+        assert_eq!(attribute(&metadata, "Status").unwrap()["value"], "claimed");
+        assert_eq!(
+            attribute(&metadata, "Claimer").unwrap()["value"],
+            claimer.as_str()
+        );
+        assert_eq!(date(&metadata, "Planted"), Some(planted));
+        assert_eq!(date(&metadata, "Claimed"), Some(planted + 10));
+        assert!(attribute(&metadata, "Retired").is_none());
+    }
+
+    #[test]
+    fn retired_metadata() {
+        let client = client();
+        let (treasure, planter) = (treasure_key(), account_key());
+        let planted = test_data::now() - 60;
+        test_data::plant(&treasure, &planter, planted);
+        test_data::retire(&treasure, &planter, planted + 10);
+
+        let metadata = get_metadata(&client, &treasure);
+        assert_eq!(attribute(&metadata, "Status").unwrap()["value"], "retired");
+        assert!(attribute(&metadata, "Claimer").is_none());
+        assert_eq!(date(&metadata, "Retired"), Some(planted + 10));
+    }
+
+    #[test]
+    fn unplanted_metadata() {
+        let client = client();
+
+        let response = client
+            .get(format!("/api/treasures/{}/metadata.json", treasure_key()))
+            .dispatch();
+        assert_ne!(response.status(), Status::Ok);
+
+        let response = client
+            .get("/api/treasures/not-a-key/metadata.json")
+            .dispatch();
+        assert_ne!(response.status(), Status::Ok);
+    }
+}
@@ -0,0 +1,122 @@
+//! Treasure records and a Rocket client for tests
+//!
+//! The records are read from `data/` under the working directory,
+//! so all tests share one temporary directory,
+//! and each test uses its own random keys.
+//! Records are written whole with a rename,
+//! so tests reading every record don't see half-written ones,
+//! and are modified at the time given,
+//! which is what the site reports as the time of the plant or claim.
+
+use crate::config::SiteConfig;
+use filetime::FileTime;
+use geonft_nostd::crypto;
+use geonft_shared::io;
+use image::{DynamicImage, ImageOutputFormat};
+use rocket::local::blocking::Client;
+use rocket::serde::json::{json, Value};
+use rocket::Route;
+use std::env;
+use std::fs::{self, File};
+use std::process;
+use std::sync::Once;
+
+pub static BASE_URL: &str = "https://treasuretree.org";
+
+static TMP_DIR: &str = "data/test-tmp";
+
+/// Change to the shared data directory, creating it on first use.
+pub fn init() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        let dir = env::temp_dir().join(format!("geonft-web-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        env::set_current_dir(&dir).unwrap();
+    });
+}
+
+pub fn site_config() -> SiteConfig {
+    SiteConfig {
+        base_url: BASE_URL.to_string(),
+        allowed_origins: vec![BASE_URL.to_string()],
+    }
+}
+
+/// A client for `routes`, serving from the shared data directory.
+pub fn client(routes: Vec<Route>) -> Client {
+    init();
+    let rocket = rocket::build().manage(site_config()).mount("/", routes);
+    Client::tracked(rocket).unwrap()
+}
+
+/// The current time in seconds since the unix epoch
+pub fn now() -> i64 {
+    chrono::Local::now().timestamp()
+}
+
+/// A new treasure public key, bech32 encoded
+pub fn treasure_key() -> String {
+    let keypair = crypto::generate_keypair(&mut rand::thread_rng());
+    crypto::encode_treasure_public_key(&keypair.public).unwrap()
+}
+
+/// A new account public key, bech32 encoded
+pub fn account_key() -> String {
+    let mnemonic = crypto::new_account_mnemonic(&mut rand::thread_rng()).unwrap();
+    let keypair = crypto::keypair_from_account_mnemonic(&mnemonic).unwrap();
+    crypto::encode_account_public_key(&keypair.public).unwrap()
+}
+
+/// A one pixel PNG, base64 encoded
+pub fn png_image() -> String {
+    let mut png = Vec::new();
+    DynamicImage::new_rgb8(1, 1)
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .unwrap();
+    base64::encode(png)
+}
+
+/// Record an unsigned plant, planted at `time`.
+pub fn plant(treasure_key: &str, account_key: &str, time: i64) {
+    let plant = json!({
+        "account_public_key": account_key,
+        "treasure_public_key": treasure_key,
+        "image": png_image(),
+        "account_signature": "",
+        "treasure_signature": "",
+    });
+    write_record(io::PLANT_DIR, treasure_key, &plant, time);
+}
+
+/// Record an unsigned claim, claimed at `time`.
+pub fn claim(treasure_key: &str, account_key: &str, time: i64) {
+    let claim = json!({
+        "account_public_key": account_key,
+        "treasure_public_key": treasure_key,
+        "account_signature": "",
+        "treasure_signature": "",
+    });
+    write_record(io::CLAIM_DIR, treasure_key, &claim, time);
+}
+
+/// Record an unsigned retirement, retired at `time`.
+pub fn retire(treasure_key: &str, account_key: &str, time: i64) {
+    let retire = json!({
+        "account_public_key": account_key,
+        "treasure_public_key": treasure_key,
+        "account_signature": "",
+    });
+    write_record(io::RETIRE_DIR, treasure_key, &retire, time);
+}
+
+fn write_record(dir: &str, key: &str, record: &Value, time: i64) {
+    fs::create_dir_all(dir).unwrap();
+    fs::create_dir_all(TMP_DIR).unwrap();
+
+    let tmp_path = format!("{}/{}-{}", TMP_DIR, dir.replace('/', "-"), key);
+    serde_json::to_writer(File::create(&tmp_path).unwrap(), record).unwrap();
+    filetime::set_file_mtime(&tmp_path, FileTime::from_unix_time(time, 0)).unwrap();
+    fs::rename(&tmp_path, format!("{}/{}", dir, key)).unwrap();
+}
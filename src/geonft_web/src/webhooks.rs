@@ -0,0 +1,631 @@
+//! Outbound webhooks for plant, claim, retire and sync events
+//!
+//! Subscriptions are read from `Rocket.toml`:
+//!
+//! ```toml
+//! [[release.webhooks]]
+//! url = "https://bots.example.com/treasuretree"
+//! secret = "shared secret"
+//! events = ["plant", "claim", "retire", "sync"]
+//! ```
+//!
+//! Every event from the `EventBus` is queued as one file per subscription
+//! in `data/webhook-queue`, so deliveries survive restarts,
+//! then POSTed as JSON with retries and exponential backoff.
+//! Each attempt is appended to `data/webhook-log/<delivery id>`.
+//! The queue and log files are written on the blocking thread pool.
+//!
+//! The body is signed with HMAC-SHA256 keyed by the subscription secret,
+//! sent hex encoded as `X-TreasureTree-Signature: sha256=<hex>`.
+
+use crate::config::SiteConfig;
+use crate::events::{EventBus, EventKind, LiveEvent};
+use anyhow::{bail, Result};
+use chrono::Local;
+use hmac::{Hmac, Mac, NewMac};
+use rocket::fairing::AdHoc;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::sync::Notify;
+use rocket::tokio::task;
+use rocket::tokio::time::{self, Duration};
+use rocket::Shutdown;
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::sync::Arc;
+
+pub static WEBHOOK_QUEUE_DIR: &'static str = "data/webhook-queue";
+pub static WEBHOOK_LOG_DIR: &'static str = "data/webhook-log";
+
+pub static SIGNATURE_HEADER: &'static str = "X-TreasureTree-Signature";
+pub static EVENT_HEADER: &'static str = "X-TreasureTree-Event";
+pub static DELIVERY_HEADER: &'static str = "X-TreasureTree-Delivery";
+
+/// Attempts before a delivery is given up on
+const MAX_ATTEMPTS: u32 = 8;
+
+/// Delay before the first retry, doubling each attempt
+const RETRY_BASE_SECS: i64 = 10;
+const RETRY_MAX_SECS: i64 = 60 * 60;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the queue is checked when nothing new is published
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Subscription {
+    pub url: String,
+    pub secret: String,
+    /// The event types to deliver, all of them by default
+    #[serde(default = "all_events")]
+    pub events: Vec<EventKind>,
+}
+
+fn all_events() -> Vec<EventKind> {
+    vec![
+        EventKind::Plant,
+        EventKind::Claim,
+        EventKind::Retire,
+        EventKind::Sync,
+    ]
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub webhooks: Vec<Subscription>,
+}
+
+impl WebhookConfig {
+    fn validate(&self) -> Result<()> {
+        for subscription in &self.webhooks {
+            if !subscription.url.starts_with("http://") && !subscription.url.starts_with("https://")
+            {
+                bail!("webhook url must be http or https: {}", subscription.url);
+            }
+            if subscription.secret.is_empty() {
+                bail!("webhook secret is empty for {}", subscription.url);
+            }
+        }
+        Ok(())
+    }
+
+    /// The subscription a delivery was queued for,
+    /// if it is still configured at the same position.
+    fn subscription(&self, delivery: &Delivery) -> Option<&Subscription> {
+        self.webhooks
+            .get(delivery.subscription)
+            .filter(|s| s.url == delivery.url)
+    }
+}
+
+/// The JSON body POSTed to subscribers
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookPayload<'a> {
+    pub delivery_id: &'a str,
+    /// Absolute URL of the treasure page
+    pub treasure_url: String,
+    pub event: &'a LiveEvent,
+}
+
+/// A queued delivery of one event to one subscriber
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Delivery {
+    pub id: String,
+    /// The index of the subscription in the config
+    pub subscription: usize,
+    pub url: String,
+    pub event: EventKind,
+    /// The serialized `WebhookPayload`, sent as is on every attempt
+    pub payload: String,
+    pub attempts: u32,
+    pub next_attempt_millis: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+struct DeliveryLogEntry<'a> {
+    delivery_id: &'a str,
+    url: &'a str,
+    event: EventKind,
+    attempt: u32,
+    /// RFC 3339
+    time: String,
+    /// The response status, if there was a response
+    status: Option<u16>,
+    error: Option<String>,
+    delivered: bool,
+    /// Set on the last failed attempt
+    gave_up: bool,
+}
+
+/// `sha256=<hex>` of the body, keyed by the subscription secret
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Seconds to wait after failed attempt number `attempts`
+fn retry_delay_secs(attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(20);
+    (RETRY_BASE_SECS << exponent).min(RETRY_MAX_SECS)
+}
+
+fn queue_deliveries(config: &WebhookConfig, base_url: &str, event: &LiveEvent) -> Result<()> {
+    fs::create_dir_all(WEBHOOK_QUEUE_DIR)?;
+
+    for (index, subscription) in config.webhooks.iter().enumerate() {
+        if !subscription.events.contains(&event.kind) {
+            continue;
+        }
+
+        let id = format!("{}-{}", event.id, index);
+        let payload = WebhookPayload {
+            delivery_id: &id,
+            treasure_url: format!("{}/{}", base_url, event.treasure_url),
+            event,
+        };
+        let delivery = Delivery {
+            subscription: index,
+            url: subscription.url.clone(),
+            event: event.kind,
+            payload: serde_json::to_string(&payload)?,
+            attempts: 0,
+            next_attempt_millis: Local::now().timestamp_millis(),
+            id,
+        };
+
+        write_delivery(&delivery)?;
+    }
+
+    Ok(())
+}
+
+fn write_delivery(delivery: &Delivery) -> Result<()> {
+    let path = format!("{}/{}", WEBHOOK_QUEUE_DIR, delivery.id);
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, delivery)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn remove_delivery(delivery: &Delivery) -> Result<()> {
+    let path = format!("{}/{}", WEBHOOK_QUEUE_DIR, delivery.id);
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+fn get_queued_deliveries() -> Result<Vec<Delivery>> {
+    fs::create_dir_all(WEBHOOK_QUEUE_DIR)?;
+
+    let mut deliveries = Vec::new();
+
+    for dent in fs::read_dir(WEBHOOK_QUEUE_DIR)? {
+        let dent = dent?;
+
+        if !dent.metadata()?.is_file() {
+            continue;
+        }
+
+        let file = File::open(dent.path())?;
+        let reader = BufReader::new(file);
+        match serde_json::from_reader(reader) {
+            Ok(delivery) => deliveries.push(delivery),
+            Err(e) => warn!("bad webhook delivery {:?}: {}", dent.path(), e),
+        }
+    }
+
+    // Most overdue first
+    deliveries.sort_by_key(|d: &Delivery| d.next_attempt_millis);
+
+    Ok(deliveries)
+}
+
+fn log_attempt(entry: &DeliveryLogEntry) -> Result<()> {
+    fs::create_dir_all(WEBHOOK_LOG_DIR)?;
+
+    let path = format!("{}/{}", WEBHOOK_LOG_DIR, entry.delivery_id);
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, entry)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// POST a delivery once, returning the response status.
+pub async fn send_delivery(
+    client: &reqwest::Client,
+    delivery: &Delivery,
+    secret: &str,
+) -> Result<u16> {
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header(EVENT_HEADER, delivery.event.name())
+        .header(DELIVERY_HEADER, &delivery.id)
+        .header(
+            SIGNATURE_HEADER,
+            sign_payload(secret, delivery.payload.as_bytes()),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await?;
+
+    Ok(response.status().as_u16())
+}
+
+/// Run file I/O on the blocking thread pool.
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f).await?
+}
+
+/// Make one attempt at a due delivery,
+/// then dequeue it or schedule the retry.
+async fn attempt_delivery(
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    delivery: Delivery,
+) -> Result<()> {
+    let subscription = match config.subscription(&delivery) {
+        Some(subscription) => subscription,
+        None => {
+            info!(
+                "dropping webhook delivery {} to unsubscribed {}",
+                delivery.id, delivery.url
+            );
+            return blocking(move || remove_delivery(&delivery)).await;
+        }
+    };
+
+    let (status, error) = match send_delivery(client, &delivery, &subscription.secret).await {
+        Ok(status) if (200..300).contains(&status) => (Some(status), None),
+        Ok(status) => (Some(status), Some(format!("status {}", status))),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    blocking(move || record_attempt(delivery, status, error)).await
+}
+
+/// Log an attempt at a delivery,
+/// then dequeue it or schedule the retry.
+fn record_attempt(
+    mut delivery: Delivery,
+    status: Option<u16>,
+    error: Option<String>,
+) -> Result<()> {
+    delivery.attempts += 1;
+    let delivered = error.is_none();
+    let gave_up = !delivered && delivery.attempts >= MAX_ATTEMPTS;
+
+    log_attempt(&DeliveryLogEntry {
+        delivery_id: &delivery.id,
+        url: &delivery.url,
+        event: delivery.event,
+        attempt: delivery.attempts,
+        time: Local::now().to_rfc3339(),
+        status,
+        error,
+        delivered,
+        gave_up,
+    })?;
+
+    if delivered || gave_up {
+        if gave_up {
+            warn!(
+                "giving up on webhook delivery {} to {}",
+                delivery.id, delivery.url
+            );
+        }
+        remove_delivery(&delivery)
+    } else {
+        delivery.next_attempt_millis =
+            Local::now().timestamp_millis() + retry_delay_secs(delivery.attempts) * 1000;
+        write_delivery(&delivery)
+    }
+}
+
+/// Attempt every due delivery,
+/// returning how long until the next one is due.
+async fn process_queue(client: &reqwest::Client, config: &WebhookConfig) -> Result<Duration> {
+    let now = Local::now().timestamp_millis();
+    let mut wait = QUEUE_POLL_INTERVAL;
+
+    for delivery in blocking(get_queued_deliveries).await? {
+        if delivery.next_attempt_millis > now {
+            let until = Duration::from_millis((delivery.next_attempt_millis - now) as u64);
+            wait = wait.min(until);
+            continue;
+        }
+
+        let id = delivery.id.clone();
+        if let Err(e) = attempt_delivery(client, config, delivery).await {
+            error!("webhook delivery {} failed: {}", id, e);
+        }
+    }
+
+    Ok(wait)
+}
+
+/// Queue a delivery for each subscriber to each event.
+async fn queue_events(
+    bus: EventBus,
+    config: Arc<WebhookConfig>,
+    base_url: String,
+    queued: Arc<Notify>,
+    mut shutdown: Shutdown,
+) {
+    let mut receiver = bus.receiver();
+
+    loop {
+        let event = select! {
+            event = receiver.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    error!("webhooks missed {} events", count);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let (config, base_url, id) = (config.clone(), base_url.clone(), event.id);
+        let queue = blocking(move || queue_deliveries(&config, &base_url, &event));
+
+        match queue.await {
+            Ok(()) => queued.notify_one(),
+            Err(e) => error!("unable to queue webhooks for event {}: {}", id, e),
+        }
+    }
+}
+
+/// Deliver queued events as they come due.
+///
+/// Deliveries left queued by the last run are retried first.
+async fn deliver_queued(config: Arc<WebhookConfig>, queued: Arc<Notify>, mut shutdown: Shutdown) {
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("unable to create webhook client: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        let wait = match process_queue(&client, &config).await {
+            Ok(wait) => wait,
+            Err(e) => {
+                error!("unable to process webhook queue: {}", e);
+                QUEUE_POLL_INTERVAL
+            }
+        };
+
+        select! {
+            _ = time::sleep(wait) => (),
+            _ = queued.notified() => (),
+            _ = &mut shutdown => break,
+        }
+    }
+}
+
+/// Read the webhook subscriptions and start delivering once launched.
+///
+/// Must be attached after the site config and event bus.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Webhooks", |rocket| async {
+        let config: WebhookConfig = match rocket.figment().extract() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("invalid webhook config: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        if let Err(e) = config.validate() {
+            error!("invalid webhook config: {}", e);
+            return Err(rocket);
+        }
+
+        info!("{} webhook subscriptions", config.webhooks.len());
+
+        let config = Arc::new(config);
+
+        Ok(
+            rocket.attach(AdHoc::on_liftoff("Webhook delivery", |rocket| {
+                Box::pin(async move {
+                    let bus = rocket.state::<EventBus>().expect("event bus").clone();
+                    let base_url = rocket
+                        .state::<SiteConfig>()
+                        .expect("site config")
+                        .base_url
+                        .clone();
+                    let queued = Arc::new(Notify::new());
+
+                    rocket::tokio::spawn(queue_events(
+                        bus,
+                        config.clone(),
+                        base_url,
+                        queued.clone(),
+                        rocket.shutdown(),
+                    ));
+                    rocket::tokio::spawn(deliver_queued(config, queued, rocket.shutdown()));
+                })
+            })),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    struct ReceivedRequest {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl ReceivedRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// Accept one request on a local listener and answer it with `status`.
+    fn listen_once(status: u16) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+
+            let mut headers = Vec::new();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_at(line.find(':').unwrap());
+                headers.push((name.to_string(), value[1..].trim().to_string()));
+            }
+
+            let length: usize = headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                .map(|(_, v)| v.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+
+            tx.send(ReceivedRequest {
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            })
+            .unwrap();
+        });
+
+        (url, rx)
+    }
+
+    fn delivery(url: &str) -> Delivery {
+        Delivery {
+            id: "1626000000000-0".to_string(),
+            subscription: 0,
+            url: url.to_string(),
+            event: EventKind::Plant,
+            payload: r#"{"delivery_id":"1626000000000-0"}"#.to_string(),
+            attempts: 0,
+            next_attempt_millis: 0,
+        }
+    }
+
+    #[test]
+    fn signature_test_vector() {
+        // HMAC-SHA256 test case 2 from RFC 4231
+        assert_eq!(
+            sign_payload("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn subscription_by_index() {
+        let subscription = |secret: &str| Subscription {
+            url: "https://bots.example.com/hook".to_string(),
+            secret: secret.to_string(),
+            events: all_events(),
+        };
+        let mut config = WebhookConfig {
+            webhooks: vec![subscription("first"), subscription("second")],
+        };
+
+        // Two subscriptions may share a url
+        let mut delivery = delivery("https://bots.example.com/hook");
+        delivery.subscription = 1;
+        assert_eq!(config.subscription(&delivery).unwrap().secret, "second");
+
+        // The subscription was removed, or another took its place
+        config.webhooks.truncate(1);
+        assert!(config.subscription(&delivery).is_none());
+        config.webhooks.push(Subscription {
+            url: "https://other.example.com/hook".to_string(),
+            ..subscription("other")
+        });
+        assert!(config.subscription(&delivery).is_none());
+    }
+
+    #[test]
+    fn retry_delays_back_off() {
+        assert_eq!(retry_delay_secs(1), 10);
+        assert_eq!(retry_delay_secs(2), 20);
+        assert_eq!(retry_delay_secs(3), 40);
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS + 100), RETRY_MAX_SECS);
+    }
+
+    #[rocket::async_test]
+    async fn delivers_signed_payload() {
+        let (url, rx) = listen_once(200);
+        let delivery = delivery(&url);
+
+        let client = reqwest::Client::new();
+        let status = send_delivery(&client, &delivery, "secret").await.unwrap();
+        assert_eq!(status, 200);
+
+        let request = rx.recv().unwrap();
+        assert_eq!(request.body, delivery.payload);
+        assert_eq!(request.header(EVENT_HEADER), Some("plant"));
+        assert_eq!(request.header(DELIVERY_HEADER), Some(delivery.id.as_str()));
+        assert_eq!(
+            request.header(SIGNATURE_HEADER),
+            Some(sign_payload("secret", delivery.payload.as_bytes()).as_str())
+        );
+    }
+
+    #[rocket::async_test]
+    async fn reports_error_status() {
+        let (url, rx) = listen_once(503);
+
+        let client = reqwest::Client::new();
+        let status = send_delivery(&client, &delivery(&url), "secret")
+            .await
+            .unwrap();
+        assert_eq!(status, 503);
+
+        rx.recv().unwrap();
+    }
+}
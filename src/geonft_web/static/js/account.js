@@ -18,10 +18,14 @@ function initAccount(callbacks) {
 }
 
 let secretKeyInput = document.getElementById("account-secret-key");
+let mnemonicInput = document.getElementById("account-mnemonic");
 let newAccountButton = document.getElementById("new-account-button");
+let recoverAccountButton = document.getElementById("recover-account-button");
 
 console.assert(secretKeyInput);
+console.assert(mnemonicInput);
 console.assert(newAccountButton);
+console.assert(recoverAccountButton);
 
 newAccountButton.addEventListener("click", async () => {
 
@@ -31,11 +35,14 @@ newAccountButton.addEventListener("click", async () => {
     try {
         let wasm = await initWasm();
 
-        let accountSecretKey_ = wasm.new_account_secret_key();
+        // Derive the key from words that are easy to write down
+        let mnemonic = wasm.new_account_mnemonic();
+        let accountSecretKey_ = wasm.account_mnemonic_to_secret_key(mnemonic);
         let publicKey = wasm.account_secret_key_to_public_key(accountSecretKey_);
 
         console.assert(publicKey);
 
+        mnemonicInput.value = mnemonic;
         secretKeyInput.value = accountSecretKey_;
         accountSecretKey = accountSecretKey_;
         accountPublicKey = publicKey;
@@ -48,7 +55,30 @@ newAccountButton.addEventListener("click", async () => {
     }
 });
 
+recoverAccountButton.addEventListener("click", async () => {
+
+    let wasm = await initWasm();
+
+    let accountSecretKey_ = wasm.account_mnemonic_to_secret_key(mnemonicInput.value);
+    if (accountSecretKey_ == null) {
+        window.alert("Those aren't valid recovery words");
+        return;
+    }
+
+    let publicKey = wasm.account_secret_key_to_public_key(accountSecretKey_);
+
+    console.assert(publicKey);
+
+    secretKeyInput.value = accountSecretKey_;
+    accountSecretKey = accountSecretKey_;
+    accountPublicKey = publicKey;
+
+    console.assert(onAccountSecretKeyChanged);
+    onAccountSecretKeyChanged();
+});
+
 secretKeyInput.addEventListener("input", async () => {
+    mnemonicInput.value = "";
     accountSecretKey = null;
     accountPublicKey = null;
     console.assert(onAccountSecretKeyChanged);
@@ -71,3 +101,149 @@ secretKeyInput.addEventListener("input", async () => {
         console.log("couldn't decode account secret key");
     }
 });
+
+let backupPassphraseInput = document.getElementById("account-backup-passphrase");
+let backupInput = document.getElementById("account-backup");
+let backupQrCodeContainer = document.getElementById("account-backup-qrcode");
+let exportAccountButton = document.getElementById("export-account-button");
+let importAccountButton = document.getElementById("import-account-button");
+
+console.assert(backupPassphraseInput);
+console.assert(backupInput);
+console.assert(backupQrCodeContainer);
+console.assert(exportAccountButton);
+console.assert(importAccountButton);
+
+exportAccountButton.addEventListener("click", async () => {
+
+    if (accountSecretKey == null) {
+        window.alert("Create or enter an account secret key to back up");
+        return;
+    }
+
+    if (backupPassphraseInput.value == "") {
+        window.alert("Enter a passphrase to encrypt the backup with");
+        return;
+    }
+
+    exportAccountButton.disabled = true;
+    importAccountButton.disabled = true;
+
+    try {
+        let wasm = await initWasm();
+
+        let backup = wasm.encrypt_account_secret_key(accountSecretKey, backupPassphraseInput.value);
+        if (backup == null) {
+            window.alert("Unable to encrypt account secret key");
+            return;
+        }
+
+        backupInput.value = backup;
+        backupQrCodeContainer.innerHTML = wasm.account_backup_to_qrcode(backup);
+    } finally {
+        exportAccountButton.disabled = false;
+        importAccountButton.disabled = false;
+    }
+});
+
+importAccountButton.addEventListener("click", async () => {
+
+    exportAccountButton.disabled = true;
+    importAccountButton.disabled = true;
+
+    try {
+        let wasm = await initWasm();
+
+        let accountSecretKey_ = wasm.decrypt_account_secret_key(backupInput.value, backupPassphraseInput.value);
+        if (accountSecretKey_ == null) {
+            window.alert("Wrong passphrase or invalid backup");
+            return;
+        }
+
+        let publicKey = wasm.account_secret_key_to_public_key(accountSecretKey_);
+
+        console.assert(publicKey);
+
+        secretKeyInput.value = accountSecretKey_;
+        accountSecretKey = accountSecretKey_;
+        accountPublicKey = publicKey;
+        backupQrCodeContainer.innerHTML = null;
+
+        console.assert(onAccountSecretKeyChanged);
+        onAccountSecretKeyChanged();
+    } finally {
+        exportAccountButton.disabled = false;
+        importAccountButton.disabled = false;
+    }
+});
+
+// Link a Solana wallet to the account, signing with the Phantom browser wallet.
+// Claimed treasures are minted to the linked wallet.
+
+let linkWalletButton = document.getElementById("link-wallet-button");
+let linkedWalletSpan = document.getElementById("account-linked-wallet");
+
+console.assert(linkWalletButton);
+console.assert(linkedWalletSpan);
+
+linkWalletButton.addEventListener("click", async () => {
+
+    if (accountSecretKey == null) {
+        window.alert("Create or enter an account secret key to link a wallet to");
+        return;
+    }
+
+    let provider = window.solana;
+    if (provider == null || !provider.isPhantom) {
+        window.alert("Install the Phantom wallet to link it");
+        return;
+    }
+
+    linkWalletButton.disabled = true;
+
+    try {
+        let wasm = await initWasm();
+
+        await provider.connect();
+        let wallet = provider.publicKey.toString();
+        let timestamp = Math.floor(Date.now() / 1000);
+
+        let message = wasm.link_wallet_message(accountPublicKey, timestamp);
+        let signed = await provider.signMessage(new TextEncoder().encode(message), "utf8");
+        let walletSignature = btoa(String.fromCharCode(...signed.signature));
+
+        let accountSignature = wasm.sign_link_wallet_with_account_secret_key(
+            accountSecretKey, wallet, timestamp);
+        console.assert(accountSignature);
+
+        let requestInfo = {
+            account_public_key: accountPublicKey,
+            wallet: wallet,
+            timestamp: timestamp,
+            account_signature: accountSignature,
+            wallet_signature: walletSignature
+        };
+
+        let response = await fetch("api/link-wallet", {
+            method: "POST",
+            headers: {
+                "Accept": "application/json",
+                "Content-Type": "application/json"
+            },
+            body: JSON.stringify(requestInfo)
+        });
+
+        if (!response.ok) {
+            let errorResponse = await response.text();
+            window.alert(errorResponse);
+            return;
+        }
+
+        linkedWalletSpan.textContent = wallet;
+    } catch (e) {
+        console.log(e);
+        window.alert("Unable to link wallet");
+    } finally {
+        linkWalletButton.disabled = false;
+    }
+});
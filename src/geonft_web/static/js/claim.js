@@ -18,8 +18,10 @@ initAccount({
 let treasureClaimed = false;
 
 let claimButton = document.getElementById("claim-button");
+let walletInput = document.getElementById("claim-wallet");
 
 console.assert(claimButton);
+console.assert(walletInput);
 
 claimButton.addEventListener("click", async () => {
 
@@ -37,8 +39,9 @@ claimButton.addEventListener("click", async () => {
     try {
         let wasm = await initWasm();
         
+        let timestamp = Math.floor(Date.now() / 1000);
         let treasureSignature = wasm.sign_claim_with_treasure_secret_key(treasureSecretKey, accountPublicKey);
-        let accountSignature = wasm.sign_claim_with_account_secret_key(accountSecretKey, treasurePublicKey);
+        let accountSignature = wasm.sign_claim_with_account_secret_key(accountSecretKey, treasurePublicKey, timestamp);
 
         if (treasureSignature == null) {
             // TODO
@@ -54,9 +57,25 @@ claimButton.addEventListener("click", async () => {
             account_public_key: accountPublicKey,
             treasure_public_key: treasurePublicKey,
             account_signature: accountSignature,
-            treasure_signature: treasureSignature
+            treasure_signature: treasureSignature,
+            timestamp: timestamp
         };
 
+        // The treasure is minted as an NFT to this wallet once synced
+        let wallet = walletInput.value.trim();
+        if (wallet != "") {
+            let walletSignature = wasm.sign_claim_wallet_with_account_secret_key(
+                accountSecretKey, treasurePublicKey, wallet);
+
+            if (walletSignature == null) {
+                window.alert("Invalid wallet address");
+                return;
+            }
+
+            requestInfo.wallet = wallet;
+            requestInfo.wallet_signature = walletSignature;
+        }
+
         let response = await fetch("api/claim", {
             method: "POST",
             headers: {
@@ -92,6 +111,122 @@ claimButton.addEventListener("click", async () => {
     }
 });
 
+// Finders can log a treasure with a comment and a photo,
+// signed by both their account and the treasure.
+
+let treasureLogged = false;
+let logImageEncoded = null;
+
+let logButton = document.getElementById("log-button");
+let logCommentInput = document.getElementById("log-comment");
+let logImageUploadButton = document.getElementById("log-image-upload-button");
+let logImageElt = document.getElementById("log-image");
+
+console.assert(logButton);
+console.assert(logCommentInput);
+console.assert(logImageUploadButton);
+console.assert(logImageElt);
+
+logCommentInput.addEventListener("input", () => {
+    maybeEnableLogButton();
+});
+
+logImageUploadButton.addEventListener("change", async () => {
+
+    logImageEncoded = null;
+    logImageElt.src = "";
+    logImageElt.classList.add("no-display");
+
+    if (logImageUploadButton.files.length == 0) {
+        return;
+    }
+
+    let file = logImageUploadButton.files[0];
+
+    let encoder = new Promise((resolve) => {
+        let reader = new FileReader();
+        reader.readAsBinaryString(file);
+        reader.addEventListener("loadend", () => {
+            resolve(reader.result);
+        });
+    });
+
+    logImageEncoded = btoa(await encoder);
+
+    logImageElt.src = URL.createObjectURL(file);
+    logImageElt.classList.remove("no-display");
+});
+
+logButton.addEventListener("click", async () => {
+
+    let logSpinner = document.getElementById("log-spinner");
+
+    console.assert(logSpinner);
+
+    logButton.disabled = true;
+    logSpinner.classList.remove("no-display");
+
+    try {
+        let wasm = await initWasm();
+
+        let comment = logCommentInput.value;
+        let imageHash = logImageEncoded == null ? undefined : wasm.get_hash(logImageEncoded);
+
+        let treasureSignature = wasm.sign_log_with_treasure_secret_key(
+            treasureSecretKey, accountPublicKey, comment, imageHash);
+        let accountSignature = wasm.sign_log_with_account_secret_key(
+            accountSecretKey, treasurePublicKey, comment, imageHash);
+
+        console.assert(treasureSignature);
+        console.assert(accountSignature);
+
+        let requestInfo = {
+            account_public_key: accountPublicKey,
+            treasure_public_key: treasurePublicKey,
+            comment: comment,
+            account_signature: accountSignature,
+            treasure_signature: treasureSignature
+        };
+
+        if (logImageEncoded != null) {
+            requestInfo.image = logImageEncoded;
+        }
+
+        let response = await fetch("api/log", {
+            method: "POST",
+            headers: {
+                "Accept": "application/json",
+                "Content-Type": "application/json"
+            },
+            body: JSON.stringify(requestInfo)
+        });
+
+        if (!response.ok) {
+            let errorResponse = await response.text();
+            window.alert(errorResponse);
+        } else {
+            treasureLogged = true;
+
+            let loggedMessageElt = document.getElementById("logged-message");
+            console.assert(loggedMessageElt);
+            loggedMessageElt.classList.remove("no-display");
+        }
+    } finally {
+        maybeEnableLogButton();
+        logSpinner.classList.add("no-display");
+    }
+});
+
+function maybeEnableLogButton() {
+    let dataReady =
+        treasureSecretKey &&
+        treasurePublicKey &&
+        accountSecretKey &&
+        logCommentInput.value.trim() != "";
+
+    logButton.disabled = !dataReady || treasureLogged;
+}
+
 function maybeEnableClaimButton() {
     let dataReady =
         treasureClaimUrl &&
@@ -106,14 +241,17 @@ function maybeEnableClaimButton() {
 
 function onAccountSecretKeyChanged() {
     maybeEnableClaimButton();
+    maybeEnableLogButton();
 }
 
 function onBeginSecretScan() {
     claimButton.disabled = true;
+    logButton.disabled = true;
 }
 
 function onEndSecretScan() {
     maybeEnableClaimButton();
+    maybeEnableLogButton();
 }
 
 async function treasureExists(){
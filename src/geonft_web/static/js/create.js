@@ -1,8 +1,59 @@
 import { initWasm } from "./wasm-init.js";
+import { getSiteConfig } from "./site-config.js";
 
 let createButton = document.getElementById("create-button");
+let eccSelect = document.getElementById("qr-ecc");
+let borderInput = document.getElementById("qr-border");
+let formatSelect = document.getElementById("qr-format");
 
 console.assert(createButton);
+console.assert(eccSelect);
+console.assert(borderInput);
+console.assert(formatSelect);
+
+let seedInput = document.getElementById("planter-seed");
+let seedIndexInput = document.getElementById("seed-index");
+let newSeedButton = document.getElementById("new-seed-button");
+
+console.assert(seedInput);
+console.assert(seedIndexInput);
+console.assert(newSeedButton);
+
+newSeedButton.addEventListener("click", async () => {
+    if (seedInput.value != ""
+        && !window.confirm("Replace the planter seed? Keys from the old seed can only be re-created with it.")) {
+        return;
+    }
+
+    let wasm = await initWasm();
+
+    seedInput.value = wasm.new_planter_seed();
+    seedIndexInput.value = 0;
+});
+
+// The planter seed and index to derive the next keys from,
+// or null to create random keys.
+function seedAndIndex() {
+    let seed = seedInput.value.trim();
+    if (seed == "") {
+        return null;
+    }
+
+    let index = parseInt(seedIndexInput.value);
+    return {
+        seed,
+        index: isNaN(index) || index < 0 ? 0 : index
+    };
+}
+
+function qrCodeOptions() {
+    let border = parseInt(borderInput.value);
+    return JSON.stringify({
+        ecc: eccSelect.value,
+        border: isNaN(border) ? 0 : border,
+        format: formatSelect.value
+    });
+}
 
 createButton.addEventListener("click", async () => {
 
@@ -21,9 +72,31 @@ createButton.addEventListener("click", async () => {
 
     try {
         let wasm = await initWasm();
-        
-        let response = wasm.create_qrcode();
-        let jsonResponse = JSON.parse(response);
+        let siteConfig = await getSiteConfig();
+
+        let seeded = seedAndIndex();
+        let jsonResponse = null;
+
+        if (seeded == null) {
+            let response = wasm.create_qrcode_with_options(siteConfig.base_url, qrCodeOptions());
+            if (response == null) {
+                window.alert("Unable to create treasure key");
+                return;
+            }
+
+            jsonResponse = JSON.parse(response);
+        } else {
+            let response = wasm.create_seeded_qrcodes(seeded.seed, seeded.index, 1,
+                                                      siteConfig.base_url, qrCodeOptions());
+            if (response == null) {
+                window.alert("Unable to create treasure key. Is the planter seed correct?");
+                return;
+            }
+
+            jsonResponse = JSON.parse(response)[0];
+            seedIndexInput.value = seeded.index + 1;
+        }
+
         console.log(jsonResponse);
         console.assert(jsonResponse.qrcode);
         console.assert(jsonResponse.secret_key);
@@ -36,3 +109,66 @@ createButton.addEventListener("click", async () => {
     }
 });
 
+let createSheetButton = document.getElementById("create-sheet-button");
+let sheetCountInput = document.getElementById("sheet-count");
+let sheetLabelInput = document.getElementById("sheet-label");
+
+console.assert(createSheetButton);
+console.assert(sheetCountInput);
+console.assert(sheetLabelInput);
+
+createSheetButton.addEventListener("click", async () => {
+
+    let sheetContainer = document.getElementById("qr-sheet");
+    let spinner = document.getElementById("create-sheet-spinner");
+
+    console.assert(sheetContainer);
+
+    sheetContainer.innerHTML = null;
+
+    let count = parseInt(sheetCountInput.value);
+    if (isNaN(count) || count < 1) {
+        window.alert("Enter how many treasure keys to create");
+        return;
+    }
+
+    createSheetButton.disabled = true;
+    spinner.classList.remove("no-display");
+
+    try {
+        let wasm = await initWasm();
+        let siteConfig = await getSiteConfig();
+
+        let seeded = seedAndIndex();
+        let response = null;
+
+        if (seeded == null) {
+            response = wasm.create_qrcode_sheet(count, sheetLabelInput.value, siteConfig.base_url, qrCodeOptions());
+        } else {
+            response = wasm.create_seeded_qrcode_sheet(seeded.seed, seeded.index, count, sheetLabelInput.value,
+                                                       siteConfig.base_url, qrCodeOptions());
+        }
+
+        if (response == null) {
+            window.alert("Unable to create treasure keys");
+            return;
+        }
+
+        if (seeded != null) {
+            seedIndexInput.value = seeded.index + count;
+        }
+
+        let jsonResponse = JSON.parse(response);
+        console.assert(jsonResponse.codes.length == count);
+
+        for (let page of jsonResponse.pages) {
+            let pageElt = document.createElement("div");
+            pageElt.classList.add("qr-sheet-page");
+            pageElt.innerHTML = page;
+            sheetContainer.appendChild(pageElt);
+        }
+    } finally {
+        createSheetButton.disabled = false;
+        spinner.classList.add("no-display");
+    }
+});
@@ -0,0 +1,84 @@
+// Live plant, claim, retire and sync activity from the server's event stream.
+//
+// New events are listed in #live-events,
+// and the sync status of any treasure on the page is kept current.
+// EventSource reconnects by itself, resuming from the last event id.
+
+const MAX_LIVE_EVENTS = 20;
+
+let liveEventsList = document.getElementById("live-events");
+
+console.assert(liveEventsList);
+
+let eventSource = new EventSource("api/events");
+
+eventSource.addEventListener("plant", (e) => {
+    let event = JSON.parse(e.data);
+    addLiveEvent(event, "Planted");
+});
+
+eventSource.addEventListener("claim", (e) => {
+    let event = JSON.parse(e.data);
+    addLiveEvent(event, "Claimed");
+
+    let section = document.getElementById(event.treasure_public_key);
+    if (section != null) {
+        section.dataset.claimed = "true";
+        setSyncStatus(section, "unsynced");
+    }
+});
+
+eventSource.addEventListener("retire", (e) => {
+    let event = JSON.parse(e.data);
+    addLiveEvent(event, "Retired");
+
+    let section = document.getElementById(event.treasure_public_key);
+    if (section != null) {
+        section.dataset.retired = "true";
+        setSyncStatus(section, "unsynced");
+    }
+});
+
+eventSource.addEventListener("sync", (e) => {
+    let event = JSON.parse(e.data);
+
+    let section = document.getElementById(event.treasure_public_key);
+    if (section == null) {
+        return;
+    }
+
+    // A retired treasure is only synced once its retirement is,
+    // and a synced plant is only the whole story if nobody has claimed it yet
+    if (section.dataset.retired == "true") {
+        if (event.sync_status == "RetireSynced") {
+            setSyncStatus(section, "synced");
+        }
+    } else if (event.sync_status == "ClaimSynced"
+        || event.sync_status == "MintSynced"
+        || (event.sync_status == "PlantSynced" && section.dataset.claimed != "true")) {
+        setSyncStatus(section, "synced");
+    }
+});
+
+function addLiveEvent(event, verb) {
+    let link = document.createElement("a");
+    link.href = event.treasure_url;
+    link.textContent = event.treasure_public_key;
+    link.classList.add("breakable");
+
+    let item = document.createElement("li");
+    item.append(verb + " ", link, " at " + new Date(event.time).toLocaleTimeString());
+
+    liveEventsList.prepend(item);
+
+    while (liveEventsList.children.length > MAX_LIVE_EVENTS) {
+        liveEventsList.lastElementChild.remove();
+    }
+}
+
+function setSyncStatus(section, status) {
+    let statusSpan = section.querySelector(".sync-status");
+    if (statusSpan != null) {
+        statusSpan.textContent = status;
+    }
+}
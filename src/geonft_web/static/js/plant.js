@@ -149,8 +149,14 @@ plantButton.addEventListener("click", async () => {
         // todo:
         // let treasureHash = wasm.get_hash(treasureImageBuffer);
 
+        let details = treasureDetails();
+        let detailsJson = details == null ? undefined : JSON.stringify(details);
+        let notBefore = claimWindowTimestamp("treasure-not-before");
+        let notAfter = claimWindowTimestamp("treasure-not-after");
+
         let treasureHash = wasm.get_hash(treasureImageEncoded);
-        let treasureSignature = wasm.sign_plant_with_treasure_secret_key(treasureSecretKey, accountPublicKey, treasureHash);
+        let treasureSignature = wasm.sign_plant_with_treasure_secret_key(
+            treasureSecretKey, accountPublicKey, treasureHash, detailsJson, notBefore, notAfter);
         let accountSignature = wasm.sign_plant_with_account_secret_key(accountSecretKey, treasurePublicKey);
         
         let requestInfo = {
@@ -160,6 +166,18 @@ plantButton.addEventListener("click", async () => {
             account_signature: accountSignature,
             treasure_signature: treasureSignature,
         };
+
+        if (details != null) {
+            requestInfo.details = details;
+        }
+
+        if (notBefore !== undefined) {
+            requestInfo.not_before = notBefore;
+        }
+
+        if (notAfter !== undefined) {
+            requestInfo.not_after = notAfter;
+        }
         
         let response = await fetch("api/plant", {
             method: "POST",
@@ -192,6 +210,47 @@ plantButton.addEventListener("click", async () => {
     }
 });
 
+// The details the treasure signature covers,
+// or null if the planter didn't give a title or hint.
+function treasureDetails() {
+    let title = document.getElementById("treasure-title").value.trim();
+    let hint = document.getElementById("treasure-hint").value.trim();
+    let difficulty = document.getElementById("treasure-difficulty").value;
+    let terrain = document.getElementById("treasure-terrain").value;
+    let expires = document.getElementById("treasure-expires").value;
+
+    if (title == "" && hint == "") {
+        return null;
+    }
+
+    let details = {
+        title: title,
+        hint: hint,
+        difficulty: parseInt(difficulty),
+        terrain: parseInt(terrain)
+    };
+
+    if (expires != "") {
+        // datetime-local is local time without an offset
+        details.expires = new Date(expires).toISOString();
+    }
+
+    return details;
+}
+
+// The unix timestamp of a claim window input,
+// or undefined if the planter left it empty.
+function claimWindowTimestamp(id) {
+    let value = document.getElementById(id).value;
+
+    if (value == "") {
+        return undefined;
+    }
+
+    // datetime-local is local time without an offset
+    return Math.floor(new Date(value).getTime() / 1000);
+}
+
 function maybeEnablePlantButton() {
     let dataReady =
         treasureImageBlob &&
@@ -0,0 +1,96 @@
+import { initWasm } from "./wasm-init.js";
+import {
+    initAccount,
+    accountSecretKey,
+    accountPublicKey
+} from "./account.js";
+
+initAccount({
+    onAccountSecretKeyChanged: onAccountSecretKeyChanged
+});
+
+let treasureRetired = false;
+
+let retireButton = document.getElementById("retire-button");
+let treasureKeyInput = document.getElementById("retire-treasure-key");
+
+console.assert(retireButton);
+console.assert(treasureKeyInput);
+
+// Linked from the treasure page as retire?treasure=<public key>
+let params = new URLSearchParams(window.location.search);
+if (params.has("treasure")) {
+    treasureKeyInput.value = params.get("treasure");
+}
+
+treasureKeyInput.addEventListener("input", () => {
+    maybeEnableRetireButton();
+});
+
+retireButton.addEventListener("click", async () => {
+
+    let retireSpinner = document.getElementById("retire-spinner");
+
+    console.assert(retireSpinner);
+
+    let treasurePublicKey = treasureKeyInput.value.trim();
+
+    if (!window.confirm("A retired treasure can never be claimed. Retire it?")) {
+        return;
+    }
+
+    retireButton.disabled = true;
+    retireSpinner.classList.remove("no-display");
+
+    try {
+        let wasm = await initWasm();
+
+        let accountSignature = wasm.sign_retire_with_account_secret_key(accountSecretKey, treasurePublicKey);
+
+        if (accountSignature == null) {
+            window.alert("Invalid treasure id");
+            return;
+        }
+
+        let requestInfo = {
+            account_public_key: accountPublicKey,
+            treasure_public_key: treasurePublicKey,
+            account_signature: accountSignature
+        };
+
+        let response = await fetch("api/retire", {
+            method: "POST",
+            headers: {
+                "Accept": "application/json",
+                "Content-Type": "application/json"
+            },
+            body: JSON.stringify(requestInfo)
+        });
+
+        if (!response.ok) {
+            let errorResponse = await response.text();
+            window.alert(errorResponse);
+        } else {
+            treasureRetired = true;
+
+            let retiredMessageElt = document.getElementById("retired-message");
+            console.assert(retiredMessageElt);
+            retiredMessageElt.classList.remove("no-display");
+        }
+    } finally {
+        maybeEnableRetireButton();
+        retireSpinner.classList.add("no-display");
+    }
+});
+
+function maybeEnableRetireButton() {
+    let dataReady =
+        treasureKeyInput.value.trim() != "" &&
+        accountSecretKey;
+
+    retireButton.disabled = !dataReady || treasureRetired;
+}
+
+function onAccountSecretKeyChanged() {
+    maybeEnableRetireButton();
+}
@@ -17,6 +17,7 @@ export {
 };
 
 import { initWasm } from "./wasm-init.js";
+import { getSiteConfig } from "./site-config.js";
 import QrScanner from "./lib/qr-scanner.min.js";
 
 /* These three globals are for access outside this file */
@@ -81,6 +82,8 @@ qrScanButton.addEventListener("click", async () => {
     treasurePublicKey = null;
 
     let wasm = await initWasm();
+    let siteConfig = await getSiteConfig();
+    let allowedOrigins = JSON.stringify(siteConfig.allowed_origins);
 
     const qrScanner = new QrScanner(video, (result) => {
         console.log(result);
@@ -89,7 +92,7 @@ qrScanButton.addEventListener("click", async () => {
         stopScanning();
 
         let url = result;
-        let sanityCheck = wasm.sanity_check_treasure_secret_url(url);
+        let sanityCheck = wasm.sanity_check_treasure_secret_url(url, allowedOrigins);
 
         if (sanityCheck === false) {
             console.error("QR code looks bogus");
@@ -143,6 +146,7 @@ qrCancelButton.addEventListener("click", async () => {
 secretKeyInput.addEventListener("input", async () => {
 
     let wasm = await initWasm();
+    let siteConfig = await getSiteConfig();
 
     console.assert(onBeginSecretScan);
     onBeginSecretScan();
@@ -153,7 +157,7 @@ secretKeyInput.addEventListener("input", async () => {
 
     let secretKey_ = secretKeyInput.value;
     let publicKey_ = wasm.treasure_secret_key_to_public_key(secretKey_);
-    let treasureClaimUrl_ = wasm.treasure_secret_key_to_secret_claim_url(secretKey_);
+    let treasureClaimUrl_ = wasm.treasure_secret_key_to_secret_claim_url(secretKey_, siteConfig.base_url);
 
     if (publicKey_ == null || treasureClaimUrl_ == null) {
         console.error("unable to decode key");
@@ -0,0 +1,22 @@
+export { getSiteConfig };
+
+let siteConfig = null;
+
+// The site's base URL and the origins whose treasure URLs are trusted,
+// from Rocket.toml.
+async function getSiteConfig() {
+    if (siteConfig != null) {
+        return siteConfig;
+    }
+
+    let response = await fetch("api/site-config");
+    if (!response.ok) {
+        throw new Error("unable to load site config: " + response.status);
+    }
+
+    siteConfig = await response.json();
+    console.assert(siteConfig.base_url);
+    console.assert(siteConfig.allowed_origins);
+
+    return siteConfig;
+}
@@ -4,6 +4,11 @@ import init, {
     set_panic_hook,
     account_secret_key_to_public_key,
     new_account_secret_key,
+    new_account_mnemonic,
+    account_mnemonic_to_secret_key,
+    encrypt_account_secret_key,
+    decrypt_account_secret_key,
+    account_backup_to_qrcode,
     sanity_check_treasure_secret_url,
     treasure_public_key_to_treasure_url,
     treasure_public_key_to_abbrev,
@@ -15,8 +20,20 @@ import init, {
     sign_plant_with_account_secret_key,
     sign_claim_with_treasure_secret_key,
     sign_claim_with_account_secret_key,
+    sign_claim_wallet_with_account_secret_key,
+    sign_retire_with_account_secret_key,
+    sign_log_with_account_secret_key,
+    sign_log_with_treasure_secret_key,
+    link_wallet_message,
+    sign_link_wallet_with_account_secret_key,
     get_hash,
-    create_qrcode
+    create_qrcode,
+    create_qrcode_with_options,
+    create_qrcodes,
+    create_qrcode_sheet,
+    new_planter_seed,
+    create_seeded_qrcodes,
+    create_seeded_qrcode_sheet
 } from "../wasm/pkg/geonft_wasm.js";
 
 let wasm = null;
@@ -32,6 +49,11 @@ async function initWasm() {
         set_panic_hook,
         account_secret_key_to_public_key,
         new_account_secret_key,
+        new_account_mnemonic,
+        account_mnemonic_to_secret_key,
+        encrypt_account_secret_key,
+        decrypt_account_secret_key,
+        account_backup_to_qrcode,
         sanity_check_treasure_secret_url,
         treasure_public_key_to_treasure_url,
         treasure_public_key_to_abbrev,
@@ -43,8 +65,20 @@ async function initWasm() {
         sign_plant_with_account_secret_key,
         sign_claim_with_treasure_secret_key,
         sign_claim_with_account_secret_key,
+        sign_claim_wallet_with_account_secret_key,
+        sign_retire_with_account_secret_key,
+        sign_log_with_account_secret_key,
+        sign_log_with_treasure_secret_key,
+        link_wallet_message,
+        sign_link_wallet_with_account_secret_key,
         get_hash,
-        create_qrcode
+        create_qrcode,
+        create_qrcode_with_options,
+        create_qrcodes,
+        create_qrcode_sheet,
+        new_planter_seed,
+        create_seeded_qrcodes,
+        create_seeded_qrcode_sheet
     };
 
     wasm.set_panic_hook();